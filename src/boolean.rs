@@ -1,68 +1,347 @@
-use crate::{GIMesh, MergeSettings, SeperateOutput, DEFAULT_VERTEX_MERGE_DISTANCE};
+use crate::{
+    error::BooleanError,
+    seperate::{seperate_sides, split_by_sides, Side},
+    slice::slice_with_bvh,
+    Classification, CsgOperation, GIMesh, MergeSettings, Predicates, SeperateOutput,
+    SeperateSettings, SliceSettings, DEFAULT_VERTEX_MERGE_DISTANCE,
+};
+
+/// One of the meshes of a [`Boolean`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operand {
+    A,
+    B,
+}
+
+/// The triangle of [`Boolean::a`] or [`Boolean::b`] a result triangle was sliced from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TriangleSource {
+    pub operand: Operand,
+    pub triangle: usize,
+}
 
 pub struct Boolean<'a> {
     pub a: &'a GIMesh,
     pub b: &'a GIMesh,
     pub vertex_merge_distance: f32,
+
+    /// How triangles are classified as inside or outside of the other mesh
+    pub classification: Classification,
+
+    /// How geometric tests are evaluated while slicing and seperating
+    pub predicates: Predicates,
+
+    /// If `true` faces of `a` and `b` lying in the same plane are detected,
+    /// keeping one copy for `union` and `intersection` and removing them for `difference`
+    ///
+    /// NOTE: `false` by default like [`SliceSettings::coplanar`] and [`SeperateSettings::coplanar`],
+    /// meshes touching each other (e.g. boxes flush against each other) need it
+    pub coplanar: bool,
 }
 
 impl<'a> Boolean<'a> {
-    /// Initializes [`Boolean`] with a default [`vertex_merge_distance`]
+    /// Initializes [`Boolean`] with a default [`vertex_merge_distance`], [`classification`] and [`predicates`]
+    ///
+    /// NOTE: [`coplanar`] is `false`, meshes with shared or flush faces need it enabled
     pub fn new(a: &'a GIMesh, b: &'a GIMesh) -> Self {
         Self {
             a,
             b,
             vertex_merge_distance: DEFAULT_VERTEX_MERGE_DISTANCE,
+            classification: Classification::default(),
+            predicates: Predicates::default(),
+            coplanar: false,
         }
     }
 
+    /// Returns the region inside of both `a` and `b`, facing outwards like the other operations
     pub fn intersection(&self) -> GIMesh {
-        let [mut ra, rb] = self.slice_and_seperate();
-        ra.inside.invert_normals().flip_windings().merge_with(
-            &rb.inside,
-            &MergeSettings {
-                merge_distance: self.vertex_merge_distance,
-                invert_b_normals: true,
-            },
-        );
-
-        ra.inside.merge_vertices(self.vertex_merge_distance)
+        self.operation(CsgOperation::Intersection)
     }
 
     pub fn difference(&self) -> GIMesh {
-        let [mut ra, rb] = self.slice_and_seperate();
-        ra.outside.merge_with(
-            &rb.inside,
-            &MergeSettings {
-                merge_distance: self.vertex_merge_distance,
-                invert_b_normals: true,
-            },
-        );
-
-        ra.outside.merge_vertices(self.vertex_merge_distance)
+        self.operation(CsgOperation::Difference)
     }
 
     pub fn union(&self) -> GIMesh {
-        let [mut ra, rb] = self.slice_and_seperate();
-        ra.outside.merge_with(
-            &rb.outside,
-            &MergeSettings {
-                merge_distance: self.vertex_merge_distance,
-                invert_b_normals: false,
-            },
-        );
+        self.operation(CsgOperation::Union)
+    }
+
+    /// Returns the regions inside exactly one of `a` and `b`
+    #[doc(alias = "xor")]
+    pub fn symmetric_difference(&self) -> GIMesh {
+        self.operation(CsgOperation::SymmetricDifference)
+    }
+
+    /// Performs `operation`
+    pub fn operation(&self, operation: CsgOperation) -> GIMesh {
+        self.with_provenance(operation).0
+    }
 
-        ra.outside.merge_vertices(self.vertex_merge_distance)
+    /// Performs `operation` if both `a` and `b` are valid, see [`Boolean::validate`]
+    pub fn try_operation(&self, operation: CsgOperation) -> Result<GIMesh, BooleanError> {
+        self.validate()?;
+        Ok(self.operation(operation))
+    }
+
+    /// Returns an error for the first of `a` and `b` that isn't closed, manifold and consistently wound,
+    /// the operations assume they are
+    ///
+    /// See [`GIMesh::validate`]
+    pub fn validate(&self) -> Result<(), BooleanError> {
+        for (operand, mesh) in [(Operand::A, self.a), (Operand::B, self.b)] {
+            let report = mesh.validate();
+            if !report.is_valid() {
+                return Err(BooleanError::InvalidOperand(operand, Box::new(report)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Performs `operation`, also returning the source of every result triangle
+    pub fn with_provenance(&self, operation: CsgOperation) -> (GIMesh, Vec<TriangleSource>) {
+        use Operand::{A, B};
+        use Side::*;
+
+        // The sides of `a` and `b` kept by `operation` in the order they're merged,
+        // and if their normals are inverted
+        let parts: &[(Operand, Side, bool)] = match operation {
+            // Faces shared by `a` and `b` are kept once
+            CsgOperation::Intersection => &[
+                (A, Inside, false),
+                (A, CoplanarSame, false),
+                (B, Inside, false),
+            ],
+
+            // Faces of `a` touching `b` from the outside are kept
+            CsgOperation::Difference => &[
+                (A, Outside, false),
+                (A, CoplanarOpposite, false),
+                (B, Inside, true),
+            ],
+
+            // Faces shared by `a` and `b` are kept once
+            CsgOperation::Union => &[
+                (A, Outside, false),
+                (A, CoplanarSame, false),
+                (B, Outside, false),
+            ],
+
+            // Faces shared by `a` and `b` don't border either region, so they're removed
+            CsgOperation::SymmetricDifference => &[
+                (A, Outside, false),
+                (A, Inside, true),
+                (B, Outside, false),
+                (B, Inside, true),
+            ],
+        };
+
+        let [ra, rb] = self.slice_and_seperate_tracked();
+        let mut output = self.a.empty_like();
+        let mut sources = Vec::new();
+        for (operand, side, invert) in parts.iter().copied() {
+            let (seperated, parents, sides) = match operand {
+                A => &ra,
+                B => &rb,
+            };
+
+            // Merging keeps the order of the triangles, so they're in the same order as `sources`
+            output.merge_with(seperated.side(side), &self.merge_settings(invert));
+            sources.extend(parents.iter().zip(sides).filter(|(_, s)| **s == side).map(
+                |(triangle, _)| TriangleSource {
+                    operand,
+                    triangle: *triangle,
+                },
+            ));
+        }
+
+        let output = output.merge_vertices(self.vertex_merge_distance);
+        debug_assert_eq!(output.tri_count(), sources.len());
+        (output, sources)
+    }
+
+    fn merge_settings(&self, invert_b_normals: bool) -> MergeSettings {
+        MergeSettings {
+            merge_distance: self.vertex_merge_distance,
+            invert_b_normals,
+        }
     }
 
     /// Useful for custom boolean operations
+    ///
+    /// NOTE: with [`coplanar`] faces shared by `a` and `b` are in the `coplanar_same` or `coplanar_opposite` of both outputs,
+    /// the operations only keep `a`'s copy
     pub fn slice_and_seperate(&self) -> [SeperateOutput; 2] {
-        let mut aa = self.a.clone();
-        let mut bb = self.b.clone();
+        self.slice_and_seperate_tracked()
+            .map(|(seperated, _, _)| seperated)
+    }
+
+    /// Like [`Boolean::slice_and_seperate`], also returning the triangle of `a` or `b`
+    /// every sliced triangle came from and the [`Side`] it was seperated to
+    fn slice_and_seperate_tracked(&self) -> [(SeperateOutput, Vec<usize>, Vec<Side>); 2] {
+        // Both outputs are in the space of `a`
+        let b = self.b.in_space_of(self.a);
+        let a_bvh = self.a.bvh();
+        let b_bvh = b.bvh();
+
+        let slice_settings = SliceSettings {
+            predicates: self.predicates,
+            coplanar: self.coplanar,
+        };
+        let seperate_settings = SeperateSettings {
+            classification: self.classification,
+            predicates: self.predicates,
+            coplanar: self.coplanar,
+        };
+
+        [(self.a, &*b, &b_bvh), (&*b, self.a, &a_bvh)].map(|(mesh, other, other_bvh)| {
+            let mut sliced = mesh.clone();
+            let parents = slice_with_bvh(&mut sliced, other, other_bvh, &slice_settings);
+            let sides = seperate_sides(&sliced, other, other_bvh, &seperate_settings);
+            (split_by_sides(&sliced, &sides), parents, sides)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Vec3, Vec3A};
+
+    use super::*;
+    use crate::test_utils::{cube, volume};
+
+    #[test]
+    fn intersection_faces_outwards() {
+        let a = cube(1.0, Vec3::ZERO);
+        let b = cube(1.0, Vec3::new(0.5, 0.25, 0.1));
+        let result = Boolean::new(&a, &b).intersection();
+
+        // A closed mesh facing outwards has a positive signed volume
+        assert!((volume(&result) - 0.5 * 0.75 * 0.9).abs() < 1e-4);
+
+        // Every face of the convex result faces away from its center,
+        // vertex normals shared by perpendicular faces are welded so they're only not inverted
+        let center = Vec3A::new(0.25, 0.125, 0.05);
+        for t in 0..result.tri_count() {
+            let [a, b, c] = result.tri(t).map(|(i, _)| result.vertex(i));
+            let normal = (b.pos - a.pos).cross(c.pos - a.pos);
+            assert!(normal.dot((a.pos + b.pos + c.pos) / 3.0 - center) > 0.0);
+            for v in [a, b, c] {
+                assert!(
+                    normal.normalize().dot(v.normal) > -1e-4,
+                    "triangle {t} faces inwards"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn provenance_points_at_source_triangles() {
+        let mut a = cube(1.0, Vec3::ZERO);
+        let mut b = cube(1.0, Vec3::new(0.5, 0.25, 0.1));
+        a.groups = vec![1; a.tri_count()];
+        b.groups = vec![2; b.tri_count()];
+
+        for operation in [
+            CsgOperation::Union,
+            CsgOperation::Difference,
+            CsgOperation::Intersection,
+            CsgOperation::SymmetricDifference,
+        ] {
+            let (result, sources) = Boolean::new(&a, &b).with_provenance(operation);
+            assert_eq!(result.tri_count(), sources.len());
+            assert!(sources.iter().any(|s| s.operand == Operand::A));
+            assert!(sources.iter().any(|s| s.operand == Operand::B));
 
-        [
-            aa.slice(self.b).seperate(self.b),
-            bb.slice(self.a).seperate(self.a),
-        ]
+            for (t, source) in sources.iter().enumerate() {
+                let mesh = match source.operand {
+                    Operand::A => &a,
+                    Operand::B => &b,
+                };
+                assert_eq!(result.group(t), mesh.group(source.triangle));
+
+                // Every result triangle lies on the plane of its source
+                let [p, q, r] = mesh.tri(source.triangle).map(|(i, _)| mesh.vertex(i).pos);
+                let normal = (q - p).cross(r - p).normalize();
+                for (i, _) in result.tri(t) {
+                    assert!(normal.dot(result.vertex(i).pos - p).abs() < 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn robust_results_dont_depend_on_translation() {
+        let volumes = |offset: Vec3| {
+            let a = cube(1.0, offset);
+            let b = cube(1.0, offset + Vec3::new(0.5, 0.25, 0.1));
+            let mut boolean = Boolean::new(&a, &b);
+            boolean.predicates = Predicates::Robust;
+            [
+                CsgOperation::Union,
+                CsgOperation::Difference,
+                CsgOperation::Intersection,
+            ]
+            .map(|operation| volume(&boolean.operation(operation)))
+        };
+
+        let expected = volumes(Vec3::ZERO);
+        assert!((expected[0] - 1.6625).abs() < 1e-4, "{expected:?}");
+        for offset in [
+            Vec3::splat(10.0),
+            Vec3::splat(1000.0),
+            Vec3::new(-37.5, 120.0, 4.25),
+        ] {
+            let volumes = volumes(offset);
+            for (volume, expected) in volumes.iter().zip(expected) {
+                assert!((volume - expected).abs() < 1e-3, "{offset} {volumes:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn coplanar_faces_are_kept_once() {
+        let a = cube(1.0, Vec3::ZERO);
+        let b = cube(1.0, Vec3::X * 0.5);
+        let mut boolean = Boolean::new(&a, &b);
+        boolean.coplanar = true;
+
+        for (operation, expected) in [
+            (CsgOperation::Union, 1.5),
+            (CsgOperation::Difference, 0.5),
+            (CsgOperation::Intersection, 0.5),
+        ] {
+            let volume = volume(&boolean.operation(operation));
+            assert!((volume - expected).abs() < 1e-4, "{operation:?} {volume}");
+        }
+
+        // Boxes flush against each other only share a face
+        let b = cube(1.0, Vec3::X);
+        let mut boolean = Boolean::new(&a, &b);
+        boolean.coplanar = true;
+        assert!((volume(&boolean.union()) - 2.0).abs() < 1e-4);
+        assert_eq!(boolean.intersection().tri_count(), 0);
+    }
+
+    #[test]
+    fn open_operands_are_rejected() {
+        let a = cube(1.0, Vec3::ZERO);
+        let mut b = cube(1.0, Vec3::X * 0.5);
+        b.indices.truncate(b.index_count() - 3);
+
+        let Err(BooleanError::InvalidOperand(operand, report)) =
+            Boolean::new(&a, &b).try_operation(CsgOperation::Union)
+        else {
+            panic!("the open operand was accepted");
+        };
+        assert_eq!(operand, Operand::B);
+        assert_eq!(report.boundary_edges.len(), 3);
+
+        let error = Boolean::new(&a, &b).validate().err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Operand B is not a closed, consistently wound mesh: 3 boundary edges"
+        );
     }
 }
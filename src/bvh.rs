@@ -0,0 +1,400 @@
+use bevy::math::Vec3A;
+
+use crate::GIMesh;
+
+/// The maximum number of triangles in a leaf node
+const LEAF_SIZE: usize = 4;
+
+/// How far away (relative to a node's size) a point has to be
+/// before the node's winding number is approximated
+const WINDING_NUMBER_ACCURACY: f32 = 2.0;
+
+/// A Bounding Volume Hierarchy over the triangles of a [`GIMesh`]
+///
+/// NOTE: the [`Bvh`] doesn't track changes, rebuild it after modifying the mesh
+#[derive(Clone)]
+pub struct Bvh {
+    pub(crate) nodes: Vec<BvhNode>,
+
+    /// Triangle indices, ordered so every leaf references a contiguous range
+    pub(crate) triangles: Vec<usize>,
+
+    /// The bounds of every triangle, in the same order as `triangles`
+    bounds: Vec<(Vec3A, Vec3A)>,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct BvhNode {
+    pub min: Vec3A,
+    pub max: Vec3A,
+
+    /// Index of the first child node, or the first triangle if this is a leaf
+    pub start: usize,
+
+    /// Number of triangles, `0` if this isn't a leaf
+    pub count: usize,
+
+    /// The sum of the area weighted normals
+    pub area_normal: Vec3A,
+
+    /// The area weighted center of the triangles
+    pub center: Vec3A,
+}
+
+impl BvhNode {
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+impl Bvh {
+    /// Builds a [`Bvh`] over the triangles of `mesh`
+    pub fn new(mesh: &GIMesh) -> Self {
+        let tri_count = mesh.tri_count();
+        let mut bvh = Self {
+            nodes: Vec::with_capacity((tri_count / LEAF_SIZE + 1) * 2),
+            triangles: (0..tri_count).collect(),
+            bounds: Vec::with_capacity(tri_count),
+        };
+
+        let tri_bounds: Vec<(Vec3A, Vec3A)> = (0..tri_count).map(|t| tri_aabb(mesh, t)).collect();
+        let centers: Vec<Vec3A> = tri_bounds
+            .iter()
+            .map(|(min, max)| (*min + *max) * 0.5)
+            .collect();
+
+        let tri_areas: Vec<(Vec3A, Vec3A)> = (0..tri_count).map(|t| tri_area(mesh, t)).collect();
+
+        bvh.nodes.push(BvhNode::default());
+        bvh.build(0, 0, tri_count, &tri_bounds, &centers, &tri_areas);
+
+        bvh.bounds = bvh.triangles.iter().map(|t| tri_bounds[*t]).collect();
+        bvh
+    }
+
+    fn build(
+        &mut self,
+        node: usize,
+        start: usize,
+        end: usize,
+        tri_bounds: &[(Vec3A, Vec3A)],
+        centers: &[Vec3A],
+        tri_areas: &[(Vec3A, Vec3A)],
+    ) {
+        let mut min = Vec3A::INFINITY;
+        let mut max = Vec3A::NEG_INFINITY;
+        let mut center_min = Vec3A::INFINITY;
+        let mut center_max = Vec3A::NEG_INFINITY;
+        for t in &self.triangles[start..end] {
+            min = min.min(tri_bounds[*t].0);
+            max = max.max(tri_bounds[*t].1);
+            center_min = center_min.min(centers[*t]);
+            center_max = center_max.max(centers[*t]);
+        }
+
+        let mut area_normal = Vec3A::ZERO;
+        let mut area_center = Vec3A::ZERO;
+        let mut area = 0.0;
+        for t in &self.triangles[start..end] {
+            let (normal, center) = tri_areas[*t];
+            let tri_area = normal.length();
+            area_normal += normal;
+            area_center += center * tri_area;
+            area += tri_area;
+        }
+
+        self.nodes[node] = BvhNode {
+            min,
+            max,
+            start: 0,
+            count: 0,
+            area_normal,
+            center: if area > 0.0 {
+                area_center / area
+            } else {
+                (min + max) * 0.5
+            },
+        };
+
+        if end - start <= LEAF_SIZE {
+            self.nodes[node].start = start;
+            self.nodes[node].count = end - start;
+            return;
+        }
+
+        // Split along the longest axis of the triangle centers
+        let extent = center_max - center_min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+
+        let mid = (start + end) / 2;
+        self.triangles[start..end].select_nth_unstable_by(mid - start, |a, b| {
+            centers[*a][axis].total_cmp(&centers[*b][axis])
+        });
+
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode::default());
+        self.nodes.push(BvhNode::default());
+        self.nodes[node].start = left;
+
+        self.build(left, start, mid, tri_bounds, centers, tri_areas);
+        self.build(left + 1, mid, end, tri_bounds, centers, tri_areas);
+    }
+
+    /// Returns the minimum and maximum x, y and z values of every triangle,
+    /// `None` if the mesh has no triangles
+    pub fn bounds(&self) -> Option<(Vec3A, Vec3A)> {
+        (!self.triangles.is_empty()).then(|| (self.nodes[0].min, self.nodes[0].max))
+    }
+
+    /// Clears `output` and fills it with every triangle whose bounds overlap `min` and `max`
+    pub fn query_aabb(&self, min: Vec3A, max: Vec3A, output: &mut Vec<usize>) {
+        output.clear();
+        self.traverse(
+            |node_min, node_max| aabb_overlap(min, max, node_min, node_max),
+            output,
+        );
+    }
+
+    /// Clears `output` and fills it with every triangle whose bounds are hit by the ray
+    ///
+    /// NOTE: only hits in the direction of `direction` are included
+    pub fn query_ray(&self, origin: Vec3A, direction: Vec3A, output: &mut Vec<usize>) {
+        output.clear();
+        self.traverse(
+            |node_min, node_max| ray_aabb(origin, direction, node_min, node_max),
+            output,
+        );
+    }
+
+    /// Returns the generalized winding number of `mesh` at `point`
+    ///
+    /// This is `1.0` inside and `0.0` outside of a closed mesh,
+    /// meshes with holes or overlaps give values in between
+    ///
+    /// NOTE: `mesh` must be the mesh this [`Bvh`] was built from
+    pub fn winding_number(&self, mesh: &GIMesh, point: Vec3A) -> f32 {
+        if self.triangles.is_empty() {
+            return 0.0;
+        }
+
+        let mut solid_angle = 0.0;
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+
+            // Far away nodes are approximated as a single dipole
+            let offset = node.center - point;
+            let distance = offset.length();
+            if distance > (node.max - node.min).length() * WINDING_NUMBER_ACCURACY {
+                solid_angle += node.area_normal.dot(offset) / (distance * distance * distance);
+                continue;
+            }
+
+            if node.is_leaf() {
+                for i in node.start..node.start + node.count {
+                    solid_angle += tri_solid_angle(mesh, self.triangles[i], point);
+                }
+            } else {
+                stack.push(node.start);
+                stack.push(node.start + 1);
+            }
+        }
+
+        solid_angle / (4.0 * std::f32::consts::PI)
+    }
+
+    fn traverse(&self, test: impl Fn(Vec3A, Vec3A) -> bool, output: &mut Vec<usize>) {
+        if self.triangles.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if !test(node.min, node.max) {
+                continue;
+            }
+
+            if node.is_leaf() {
+                for i in node.start..node.start + node.count {
+                    if test(self.bounds[i].0, self.bounds[i].1) {
+                        output.push(self.triangles[i]);
+                    }
+                }
+            } else {
+                stack.push(node.start);
+                stack.push(node.start + 1);
+            }
+        }
+    }
+}
+
+/// Returns the minimum and maximum x, y and z values of triangle `t`
+pub(crate) fn tri_aabb(mesh: &GIMesh, t: usize) -> (Vec3A, Vec3A) {
+    let tri = mesh.tri(t);
+    let a = mesh.vertex(tri[0].0).pos;
+    let b = mesh.vertex(tri[1].0).pos;
+    let c = mesh.vertex(tri[2].0).pos;
+
+    (a.min(b).min(c), a.max(b).max(c))
+}
+
+/// Returns the area weighted normal and the center of triangle `t`
+fn tri_area(mesh: &GIMesh, t: usize) -> (Vec3A, Vec3A) {
+    let tri = mesh.tri(t);
+    let a = mesh.vertex(tri[0].0).pos;
+    let b = mesh.vertex(tri[1].0).pos;
+    let c = mesh.vertex(tri[2].0).pos;
+
+    ((b - a).cross(c - a) * 0.5, (a + b + c) / 3.0)
+}
+
+/// Returns the signed solid angle of triangle `t` seen from `point`
+fn tri_solid_angle(mesh: &GIMesh, t: usize, point: Vec3A) -> f32 {
+    let tri = mesh.tri(t);
+    let a = mesh.vertex(tri[0].0).pos - point;
+    let b = mesh.vertex(tri[1].0).pos - point;
+    let c = mesh.vertex(tri[2].0).pos - point;
+
+    let (la, lb, lc) = (a.length(), b.length(), c.length());
+    let numerator = a.dot(b.cross(c));
+    let denominator = la * lb * lc + a.dot(b) * lc + a.dot(c) * lb + b.dot(c) * la;
+
+    2.0 * numerator.atan2(denominator)
+}
+
+pub(crate) fn aabb_overlap(a_min: Vec3A, a_max: Vec3A, b_min: Vec3A, b_max: Vec3A) -> bool {
+    a_min.cmple(b_max).all() && a_max.cmpge(b_min).all()
+}
+
+fn ray_aabb(origin: Vec3A, direction: Vec3A, min: Vec3A, max: Vec3A) -> bool {
+    let mut t_min = 0.0f32;
+    let mut t_max = f32::INFINITY;
+
+    for axis in 0..3 {
+        let o = origin[axis];
+        let d = direction[axis];
+        if d == 0.0 {
+            if o < min[axis] || o > max[axis] {
+                return false;
+            }
+
+            continue;
+        }
+
+        let t1 = (min[axis] - o) / d;
+        let t2 = (max[axis] - o) / d;
+        t_min = t_min.max(t1.min(t2));
+        t_max = t_max.min(t1.max(t2));
+        if t_min > t_max {
+            return false;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Vec3, Vec3A};
+
+    use super::*;
+    use crate::test_utils::{cube, sphere};
+
+    #[test]
+    fn query_aabb_matches_brute_force() {
+        let mut mesh = sphere(1.0);
+        mesh.merge_with(&cube(0.5, Vec3::new(1.0, 0.2, 0.0)), &Default::default());
+        let bvh = Bvh::new(&mesh);
+
+        let mut output = Vec::new();
+        for (min, max) in [
+            (Vec3A::splat(-0.1), Vec3A::splat(0.1)),
+            (Vec3A::new(0.5, -1.0, -1.0), Vec3A::new(2.0, 1.0, 1.0)),
+            (Vec3A::new(-1.0, 0.9, -0.2), Vec3A::new(1.0, 1.5, 0.2)),
+            (Vec3A::splat(3.0), Vec3A::splat(4.0)),
+        ] {
+            bvh.query_aabb(min, max, &mut output);
+            output.sort_unstable();
+
+            let expected: Vec<usize> = (0..mesh.tri_count())
+                .filter(|t| {
+                    let (t_min, t_max) = tri_aabb(&mesh, *t);
+                    aabb_overlap(min, max, t_min, t_max)
+                })
+                .collect();
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn query_ray_matches_brute_force() {
+        let mesh = sphere(1.0);
+        let bvh = Bvh::new(&mesh);
+
+        let mut output = Vec::new();
+        for (origin, direction) in [
+            (Vec3A::ZERO, Vec3A::X),
+            (Vec3A::new(-2.0, 0.3, 0.1), Vec3A::X),
+            (Vec3A::new(0.2, 2.0, -0.4), Vec3A::new(0.1, -1.0, 0.3)),
+            (Vec3A::new(2.0, 2.0, 2.0), Vec3A::ONE),
+        ] {
+            bvh.query_ray(origin, direction, &mut output);
+            output.sort_unstable();
+
+            let expected: Vec<usize> = (0..mesh.tri_count())
+                .filter(|t| {
+                    let (t_min, t_max) = tri_aabb(&mesh, *t);
+                    ray_aabb(origin, direction, t_min, t_max)
+                })
+                .collect();
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn winding_number_inside_and_outside() {
+        let mesh = sphere(1.0);
+        let bvh = Bvh::new(&mesh);
+
+        for direction in [Vec3A::X, Vec3A::new(0.3, -0.5, 0.8).normalize()] {
+            for distance in [0.0, 0.3, 0.6, 0.9] {
+                let winding = bvh.winding_number(&mesh, direction * distance);
+                assert!((winding - 1.0).abs() < 0.01, "{distance}: {winding}");
+            }
+
+            for distance in [1.1, 1.5, 3.0, 10.0, 100.0] {
+                let winding = bvh.winding_number(&mesh, direction * distance);
+                assert!(winding.abs() < 0.01, "{distance}: {winding}");
+            }
+        }
+    }
+
+    #[test]
+    fn winding_number_at_approximation_cutoff() {
+        let mesh = sphere(1.0);
+        let bvh = Bvh::new(&mesh);
+        let root = &bvh.nodes[0];
+        let cutoff = (root.max - root.min).length() * WINDING_NUMBER_ACCURACY;
+
+        // Just inside the cutoff the root is evaluated exactly, just outside it's a dipole
+        for distance in [cutoff * 0.99, cutoff, cutoff * 1.01] {
+            let point = root.center + Vec3A::new(0.6, 0.0, 0.8) * distance;
+            let winding = bvh.winding_number(&mesh, point);
+            assert!(winding.abs() < 1e-3, "{distance}: {winding}");
+        }
+
+        // Child nodes close to the surface are approximated while the rest is evaluated exactly
+        for distance in [0.95, 1.05] {
+            let winding = bvh.winding_number(&mesh, Vec3A::Y * distance);
+            let expected = if distance < 1.0 { 1.0 } else { 0.0 };
+            assert!((winding - expected).abs() < 0.01, "{distance}: {winding}");
+        }
+    }
+}
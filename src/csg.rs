@@ -0,0 +1,299 @@
+use bevy::math::Affine3A;
+
+use crate::{Boolean, Classification, GIMesh, Predicates, DEFAULT_VERTEX_MERGE_DISTANCE};
+
+/// The [`Boolean`] operation performed by a [`CsgTree`] node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsgOperation {
+    Union,
+    Difference,
+    Intersection,
+    SymmetricDifference,
+}
+
+/// A node in a [`CsgTree`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CsgNodeId(usize);
+
+enum CsgNodeKind {
+    Leaf {
+        mesh: Box<GIMesh>,
+        transform: Affine3A,
+    },
+    Operation {
+        operation: CsgOperation,
+        a: CsgNodeId,
+        b: CsgNodeId,
+    },
+}
+
+struct CsgNode {
+    kind: CsgNodeKind,
+
+    /// Every node using this node as an operand
+    parents: Vec<CsgNodeId>,
+
+    /// The result of this node, `None` if it has to be evaluated
+    cache: Option<GIMesh>,
+}
+
+/// A tree of [`Boolean`] operations over [`GIMesh`] leaves
+///
+/// Results are evaluated when requested and cached,
+/// changing a leaf only reevaluates the nodes using it
+pub struct CsgTree {
+    nodes: Vec<CsgNode>,
+
+    pub vertex_merge_distance: f32,
+
+    /// See [`Boolean::classification`]
+    pub classification: Classification,
+
+    /// See [`Boolean::predicates`]
+    ///
+    /// NOTE: [`Predicates::Robust`] by default, [`Predicates::Fast`] slices by the vertex normals,
+    /// which don't match their faces after the vertices of a nested result are merged
+    pub predicates: Predicates,
+
+    /// See [`Boolean::coplanar`]
+    pub coplanar: bool,
+}
+
+impl Default for CsgTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsgTree {
+    /// Initializes an empty [`CsgTree`] with the same defaults as [`Boolean::new`],
+    /// except for [`Predicates::Robust`]
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            vertex_merge_distance: DEFAULT_VERTEX_MERGE_DISTANCE,
+            classification: Classification::default(),
+            predicates: Predicates::Robust,
+            coplanar: false,
+        }
+    }
+
+    /// Adds a leaf of `mesh` transformed by `transform`
+    pub fn leaf(&mut self, mesh: GIMesh, transform: Affine3A) -> CsgNodeId {
+        self.add_node(CsgNodeKind::Leaf {
+            mesh: Box::new(mesh),
+            transform,
+        })
+    }
+
+    /// Adds a node performing `operation` on `a` and `b`
+    pub fn operation(&mut self, operation: CsgOperation, a: CsgNodeId, b: CsgNodeId) -> CsgNodeId {
+        let id = self.add_node(CsgNodeKind::Operation { operation, a, b });
+        self.nodes[a.0].parents.push(id);
+        self.nodes[b.0].parents.push(id);
+        id
+    }
+
+    pub fn union(&mut self, a: CsgNodeId, b: CsgNodeId) -> CsgNodeId {
+        self.operation(CsgOperation::Union, a, b)
+    }
+
+    pub fn difference(&mut self, a: CsgNodeId, b: CsgNodeId) -> CsgNodeId {
+        self.operation(CsgOperation::Difference, a, b)
+    }
+
+    pub fn intersection(&mut self, a: CsgNodeId, b: CsgNodeId) -> CsgNodeId {
+        self.operation(CsgOperation::Intersection, a, b)
+    }
+
+    pub fn symmetric_difference(&mut self, a: CsgNodeId, b: CsgNodeId) -> CsgNodeId {
+        self.operation(CsgOperation::SymmetricDifference, a, b)
+    }
+
+    fn add_node(&mut self, kind: CsgNodeKind) -> CsgNodeId {
+        self.nodes.push(CsgNode {
+            kind,
+            parents: Vec::new(),
+            cache: None,
+        });
+
+        CsgNodeId(self.nodes.len() - 1)
+    }
+
+    /// Replaces the mesh of `leaf`
+    ///
+    /// NOTE: does nothing if `leaf` isn't a leaf
+    pub fn set_mesh(&mut self, leaf: CsgNodeId, mesh: GIMesh) {
+        if let CsgNodeKind::Leaf { mesh: old, .. } = &mut self.nodes[leaf.0].kind {
+            **old = mesh;
+            self.invalidate(leaf);
+        }
+    }
+
+    /// Replaces the transform of `leaf`
+    ///
+    /// NOTE: does nothing if `leaf` isn't a leaf
+    pub fn set_transform(&mut self, leaf: CsgNodeId, transform: Affine3A) {
+        if let CsgNodeKind::Leaf { transform: old, .. } = &mut self.nodes[leaf.0].kind {
+            *old = transform;
+            self.invalidate(leaf);
+        }
+    }
+
+    /// Replaces the operation of `node`
+    ///
+    /// NOTE: does nothing if `node` is a leaf
+    pub fn set_operation(&mut self, node: CsgNodeId, operation: CsgOperation) {
+        if let CsgNodeKind::Operation { operation: old, .. } = &mut self.nodes[node.0].kind {
+            *old = operation;
+            self.invalidate(node);
+        }
+    }
+
+    /// Clears the cached result of `node` and every node using it
+    pub fn invalidate(&mut self, node: CsgNodeId) {
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            let node = &mut self.nodes[node.0];
+            if node.cache.take().is_some() {
+                stack.extend_from_slice(&node.parents);
+            }
+        }
+    }
+
+    /// Clears every cached result
+    pub fn invalidate_all(&mut self) {
+        for node in &mut self.nodes {
+            node.cache = None;
+        }
+    }
+
+    /// Returns `true` if `node` has a cached result
+    pub fn is_cached(&self, node: CsgNodeId) -> bool {
+        self.nodes[node.0].cache.is_some()
+    }
+
+    /// Returns the result of `node`, only evaluating nodes without a cached result
+    ///
+    /// NOTE: results are in the space the leaves are transformed into,
+    /// [`GIMesh::to_mesh`] returns them with every leaf transform applied
+    pub fn evaluate(&mut self, node: CsgNodeId) -> &GIMesh {
+        if self.nodes[node.0].cache.is_none() {
+            let result = match self.nodes[node.0].kind {
+                CsgNodeKind::Leaf {
+                    ref mesh,
+                    transform,
+                } => {
+                    // The result is in the space of the tree, `to_mesh` doesn't undo `transform`
+                    let mut mesh = GIMesh::clone(mesh);
+                    mesh.transform(transform).inverse_model = Affine3A::IDENTITY;
+                    mesh
+                }
+                CsgNodeKind::Operation { operation, a, b } => {
+                    self.evaluate(a);
+                    self.evaluate(b);
+
+                    let boolean = Boolean {
+                        a: self.nodes[a.0].cache.as_ref().unwrap(),
+                        b: self.nodes[b.0].cache.as_ref().unwrap(),
+                        vertex_merge_distance: self.vertex_merge_distance,
+                        classification: self.classification,
+                        predicates: self.predicates,
+                        coplanar: self.coplanar,
+                    };
+
+                    boolean.operation(operation)
+                }
+            };
+
+            self.nodes[node.0].cache = Some(result);
+        }
+
+        self.nodes[node.0].cache.as_ref().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        math::Vec3,
+        render::mesh::{Mesh, VertexAttributeValues},
+    };
+
+    use super::*;
+    use crate::test_utils::{cube, volume};
+
+    #[test]
+    fn leaf_transforms_are_kept_by_to_mesh() {
+        let mut tree = CsgTree::new();
+        let a = tree.leaf(
+            cube(1.0, Vec3::ZERO),
+            Affine3A::from_translation(Vec3::X * 5.0),
+        );
+        let b = tree.leaf(
+            cube(1.0, Vec3::ZERO),
+            Affine3A::from_translation(Vec3::X * 5.5),
+        );
+        let union = tree.union(a, b);
+
+        let mesh = tree.evaluate(union).to_mesh_ref().unwrap();
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("missing positions");
+        };
+
+        let (min, max) = positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), p| (min.min(Vec3::from(*p)), max.max(Vec3::from(*p))),
+        );
+        assert!(min.abs_diff_eq(Vec3::new(4.5, -0.5, -0.5), 1e-5), "{min}");
+        assert!(max.abs_diff_eq(Vec3::new(6.0, 0.5, 0.5), 1e-5), "{max}");
+    }
+
+    #[test]
+    fn nested_operations() {
+        let mut tree = CsgTree::new();
+        let a = tree.leaf(cube(1.0, Vec3::ZERO), Affine3A::IDENTITY);
+        let b = tree.leaf(
+            cube(1.0, Vec3::ZERO),
+            Affine3A::from_translation(Vec3::new(0.5, 0.25, 0.1)),
+        );
+        let c = tree.leaf(
+            cube(1.0, Vec3::ZERO),
+            Affine3A::from_translation(Vec3::new(0.25, 0.6, 0.3)),
+        );
+        let ab = tree.union(a, b);
+        let union = tree.union(ab, c);
+        let difference = tree.difference(ab, c);
+
+        // By inclusion-exclusion of the pairwise and triple overlaps
+        assert!((volume(tree.evaluate(union)) - 2.2025).abs() < 1e-4);
+        assert!((volume(tree.evaluate(difference)) - 1.2025).abs() < 1e-4);
+    }
+
+    #[test]
+    fn changing_a_leaf_only_invalidates_its_ancestors() {
+        let mut tree = CsgTree::new();
+        let a = tree.leaf(cube(1.0, Vec3::ZERO), Affine3A::IDENTITY);
+        let b = tree.leaf(cube(1.0, Vec3::X * 0.5), Affine3A::IDENTITY);
+        let c = tree.leaf(cube(1.0, Vec3::Y * 0.5), Affine3A::IDENTITY);
+        let d = tree.leaf(cube(1.0, Vec3::Z * 0.5), Affine3A::IDENTITY);
+        let ab = tree.union(a, b);
+        let cd = tree.union(c, d);
+        let root = tree.difference(ab, cd);
+        tree.evaluate(root);
+
+        let nodes = [a, b, c, d, ab, cd, root];
+        assert!(nodes.iter().all(|node| tree.is_cached(*node)));
+
+        tree.set_transform(a, Affine3A::from_translation(Vec3::NEG_X));
+        let cached = nodes.map(|node| tree.is_cached(node));
+        assert_eq!(cached, [false, true, true, true, false, true, false]);
+
+        tree.evaluate(root);
+        tree.set_mesh(d, cube(2.0, Vec3::ZERO));
+        let cached = nodes.map(|node| tree.is_cached(node));
+        assert_eq!(cached, [true, true, true, false, true, false, false]);
+    }
+}
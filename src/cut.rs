@@ -0,0 +1,335 @@
+use bevy::{
+    math::{Vec2, Vec3A},
+    utils::HashMap,
+};
+
+use crate::{
+    predicates::{dvec3, tolerance},
+    slice::slice_by_plane,
+    GIMesh, SpatialHash, Vertex,
+};
+
+/// An infinite plane, every point `p` on the plane satisfies `normal.dot(p) == distance`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CutPlane {
+    pub normal: Vec3A,
+    pub distance: f32,
+}
+
+impl CutPlane {
+    pub fn new(normal: Vec3A, distance: f32) -> Self {
+        Self { normal, distance }
+    }
+
+    /// Creates a [`CutPlane`] through `point` facing `normal`
+    pub fn from_point_normal(point: Vec3A, normal: Vec3A) -> Self {
+        let normal = normal.normalize_or_zero();
+        Self {
+            normal,
+            distance: normal.dot(point),
+        }
+    }
+
+    /// Returns a point on the plane
+    pub fn point(&self) -> Vec3A {
+        self.normal * self.distance / self.normal.length_squared()
+    }
+}
+
+pub struct CutOutput {
+    /// The half on the side `normal` is facing
+    pub above: GIMesh,
+
+    /// The half on the opposite side
+    pub below: GIMesh,
+}
+
+/// How the attributes of cap faces are generated
+///
+/// Caps always get flat normals facing out of their half,
+/// attributes the mesh doesn't have aren't added
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CapSettings {
+    /// If `true` `uv0` is projected onto the cap plane, otherwise it's interpolated along the cut edges
+    ///
+    /// NOTE: ignored if the mesh has no `uv0`, the other vertices would be missing it
+    pub planar_uvs: bool,
+
+    /// World units to UV units of the planar UVs
+    pub uv_scale: Vec2,
+    pub uv_offset: Vec2,
+
+    /// If `true` tangents follow the U axis of the planar UVs, otherwise they're interpolated along the cut edges
+    ///
+    /// NOTE: ignored if the mesh has no tangents, see [`GIMesh::generate_tangents`] to add them
+    pub generate_tangents: bool,
+
+    /// The group of the cap triangles, see [`GIMesh::groups`]
+    pub group: u32,
+}
+
+impl Default for CapSettings {
+    fn default() -> Self {
+        Self {
+            planar_uvs: true,
+            uv_scale: Vec2::ONE,
+            uv_offset: Vec2::ZERO,
+            generate_tangents: true,
+            group: 0,
+        }
+    }
+}
+
+impl CapSettings {
+    /// Returns the cap vertex at `vertex` of a cap facing `normal`, with `u` as its U axis
+    fn cap_vertex(&self, vertex: &Vertex, normal: Vec3A, u: Vec3A) -> Vertex {
+        let v = normal.cross(u);
+        let mut vertex = Vertex {
+            normal,
+            ..vertex.clone()
+        };
+
+        if let Some(uv0) = &mut vertex.uv0 {
+            if self.planar_uvs {
+                *uv0 = Vec2::new(vertex.pos.dot(u), vertex.pos.dot(v)) * self.uv_scale
+                    + self.uv_offset;
+            }
+        }
+
+        if let Some(tangent) = &mut vertex.tangent {
+            if self.generate_tangents {
+                *tangent = u.extend(1.0);
+            }
+        }
+
+        vertex
+    }
+}
+
+/// Cuts `mesh` by `plane`, closing the cut in both halves with cap faces
+///
+/// NOTE: Only closed meshes get closed halves
+pub fn cut_by_plane(mesh: &GIMesh, plane: CutPlane, settings: &CapSettings) -> CutOutput {
+    let normal = plane.normal.normalize_or_zero();
+    let point = plane.point();
+
+    let mut sliced = mesh.clone();
+    slice_by_plane(&mut sliced, dvec3(point), dvec3(normal));
+
+    let side = |pos: Vec3A| (dvec3(pos) - dvec3(point)).dot(dvec3(normal));
+    let on_plane = |pos: Vec3A| side(pos).abs() <= tolerance(&[dvec3(pos), dvec3(point)]);
+
+    let mut output = CutOutput {
+        above: sliced.empty_like(),
+        below: sliced.empty_like(),
+    };
+
+    let mut above_map = vec![None; sliced.vertices.len()];
+    let mut below_map = vec![None; sliced.vertices.len()];
+    let mut cut_edges = Vec::new();
+    for t in 0..sliced.tri_count() {
+        let tri = sliced.tri(t);
+        let center = tri
+            .iter()
+            .map(|(i, _)| sliced.vertex(*i).pos)
+            .sum::<Vec3A>()
+            / 3.0;
+
+        let (half, map) = if side(center) > 0.0 {
+            (&mut output.above, &mut above_map)
+        } else {
+            (&mut output.below, &mut below_map)
+        };
+
+        for (i, _) in tri {
+            let index =
+                *map[i as usize].get_or_insert_with(|| half.add_vertex(sliced.vertex(i).clone()));
+            half.add_index(index);
+        }
+        half.set_group(half.tri_count() - 1, sliced.group(t));
+
+        // The edges of `below` lying on the plane are where the cut is
+        if side(center) <= 0.0 {
+            for e in 0..3 {
+                let (a, b) = (tri[e].0, tri[(e + 1) % 3].0);
+                if on_plane(sliced.vertex(a).pos) && on_plane(sliced.vertex(b).pos) {
+                    cut_edges.push((a, b));
+                }
+            }
+        }
+    }
+
+    // Both caps share the U axis, so their planar UVs line up
+    let loops = cut_loops(&sliced, &cut_edges);
+    let u = normal.any_orthonormal_vector();
+    for (half, cap_normal) in [(&mut output.below, normal), (&mut output.above, -normal)] {
+        let caps: Vec<Vec<Vertex>> = loops
+            .iter()
+            .map(|cut_loop| {
+                let mut cap: Vec<Vertex> = cut_loop
+                    .iter()
+                    .map(|i| settings.cap_vertex(sliced.vertex(*i), cap_normal, u))
+                    .collect();
+
+                // The cap of `above` faces the other way
+                if cap_normal != normal {
+                    cap.reverse();
+                }
+                cap
+            })
+            .collect();
+
+        half.add_polygon(&caps, cap_normal, settings.group);
+    }
+
+    output
+}
+
+/// Joins the directed `edges` into closed loops, facing the opposite way of the edges
+fn cut_loops(mesh: &GIMesh, edges: &[(u32, u32)]) -> Vec<Vec<u32>> {
+    // Vertices at the same position are joined
+    let distance = edges
+        .iter()
+        .map(|(a, _)| tolerance(&[dvec3(mesh.vertex(*a).pos)]) as f32)
+        .fold(0.0, f32::max);
+    let mut hash = SpatialHash::new(distance);
+    let mut points: Vec<u32> = Vec::new();
+    let mut point_of = |i: u32, hash: &mut SpatialHash| {
+        let pos = mesh.vertex(i).pos;
+        let existing = hash
+            .nearby(pos)
+            .find(|p| mesh.vertex(points[*p as usize]).pos.distance(pos) <= distance);
+
+        existing.unwrap_or_else(|| {
+            points.push(i);
+            hash.insert(pos, points.len() as u32 - 1);
+            points.len() as u32 - 1
+        })
+    };
+
+    // An edge used in both directions isn't on the border of the cut
+    let mut next: HashMap<u32, Vec<u32>> = HashMap::default();
+    for (a, b) in edges {
+        let (a, b) = (point_of(*a, &mut hash), point_of(*b, &mut hash));
+        if a == b {
+            continue;
+        }
+
+        if let Some(n) = next.get_mut(&b) {
+            if let Some(i) = n.iter().position(|n| *n == a) {
+                n.swap_remove(i);
+                continue;
+            }
+        }
+
+        next.entry(a).or_default().push(b);
+    }
+
+    let mut loops = Vec::new();
+    let mut starts: Vec<u32> = next.keys().copied().collect();
+    starts.sort_unstable();
+    for start in starts {
+        while let Some(mut current) = next.get_mut(&start).and_then(|n| n.pop()) {
+            let mut cut_loop = vec![start];
+            while current != start {
+                cut_loop.push(current);
+                let Some(n) = next.get_mut(&current).and_then(|n| n.pop()) else {
+                    break;
+                };
+                current = n;
+            }
+
+            if current == start && cut_loop.len() >= 3 {
+                cut_loop.reverse();
+                loops.push(cut_loop.iter().map(|p| points[*p as usize]).collect());
+            }
+        }
+    }
+
+    loops
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+    use crate::test_utils::{cube, volume};
+
+    #[test]
+    fn cut_closed_cube_through_middle() {
+        let mesh = cube(1.0, Vec3::ZERO);
+        for normal in [Vec3A::X, Vec3A::new(1.0, 2.0, 0.5).normalize()] {
+            let plane = CutPlane::from_point_normal(Vec3A::ZERO, normal);
+            let output = mesh.cut_by_plane(plane);
+
+            for (half, cap_normal) in [(&output.below, normal), (&output.above, -normal)] {
+                let report = half.validate();
+                assert!(report.is_valid(), "{report}");
+
+                // The cut goes through the center, so both halves have half the volume
+                assert!((volume(half) - 0.5).abs() < 1e-4);
+
+                let mut caps = 0;
+                for t in 0..half.tri_count() {
+                    let [a, b, c] = half.tri(t).map(|(i, _)| half.vertex(i));
+                    if [a, b, c].iter().all(|v| normal.dot(v.pos).abs() < 1e-5) {
+                        caps += 1;
+                        let face = (b.pos - a.pos).cross(c.pos - a.pos).normalize();
+                        assert!(face.abs_diff_eq(cap_normal, 1e-4), "{face} {cap_normal}");
+                        for v in [a, b, c] {
+                            assert_eq!(v.normal, cap_normal);
+                        }
+                    }
+                }
+                assert!(caps > 0);
+            }
+        }
+    }
+
+    /// Returns the vertices of the triangles of `half` lying on `plane`
+    fn cap_vertices(half: &GIMesh, plane: CutPlane) -> Vec<Vertex> {
+        (0..half.tri_count())
+            .map(|t| half.tri(t).map(|(i, _)| half.vertex(i).clone()))
+            .filter(|tri| tri.iter().all(|v| plane.normal.dot(v.pos).abs() < 1e-5))
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn cap_settings_only_replace_existing_attributes() {
+        let plane = CutPlane::new(Vec3A::Y, 0.0);
+        let settings = CapSettings {
+            uv_scale: Vec2::splat(2.0),
+            uv_offset: Vec2::new(0.5, 0.25),
+            ..Default::default()
+        };
+        let u = plane.normal.any_orthonormal_vector();
+        let v = plane.normal.cross(u);
+
+        // The cube has UVs but no tangents
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        let output = mesh.cut_by_plane_with(plane, &settings);
+        let caps = cap_vertices(&output.below, plane);
+        assert!(!caps.is_empty());
+        for vertex in &caps {
+            let uv = Vec2::new(vertex.pos.dot(u), vertex.pos.dot(v)) * 2.0 + Vec2::new(0.5, 0.25);
+            assert!(vertex.uv0.unwrap().abs_diff_eq(uv, 1e-5));
+            assert_eq!(vertex.tangent, None);
+        }
+
+        mesh.generate_tangents().unwrap();
+        let output = mesh.cut_by_plane_with(plane, &settings);
+        for vertex in cap_vertices(&output.below, plane) {
+            assert_eq!(vertex.tangent, Some(u.extend(1.0)));
+        }
+
+        for vertex in &mut mesh.vertices {
+            vertex.uv0 = None;
+        }
+        let output = mesh.cut_by_plane_with(plane, &settings);
+        for vertex in cap_vertices(&output.below, plane) {
+            assert_eq!(vertex.uv0, None);
+        }
+    }
+}
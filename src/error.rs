@@ -1,4 +1,6 @@
-use bevy::utils::thiserror::Error;
+use bevy::{render::render_resource::PrimitiveTopology, utils::thiserror::Error};
+
+use crate::{Operand, ValidationReport};
 
 #[derive(Error, Debug)]
 pub enum ConvertError {
@@ -19,4 +21,22 @@ pub enum ConvertError {
 
     #[error("A vertex is missing an expected attribute")]
     VertexMissingAttribute,
+
+    #[error("The primitive topology {0:?} is not supported")]
+    UnsupportedTopology(PrimitiveTopology),
+}
+
+#[derive(Error, Debug)]
+pub enum GenerateTangentsError {
+    #[error("A vertex has no uv0")]
+    MissingUv0,
+
+    #[error("MikkTSpace failed to generate tangents")]
+    MikkTSpace,
+}
+
+#[derive(Error, Debug)]
+pub enum BooleanError {
+    #[error("Operand {0:?} is not a closed, consistently wound mesh: {}", .1)]
+    InvalidOperand(Operand, Box<ValidationReport>),
 }
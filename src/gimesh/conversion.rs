@@ -1,24 +1,51 @@
 use bevy::{
-    math::Affine3A,
+    math::{Affine3A, DAffine3, DVec3, Vec3A, Vec4, Vec4Swizzles},
     render::{
-        mesh::{Indices, Mesh, VertexAttributeValues},
+        mesh::{Indices, Mesh, MeshVertexAttribute, MeshVertexAttributeId, VertexAttributeValues},
         render_asset::RenderAssetUsages,
-        render_resource::PrimitiveTopology,
+        render_resource::{PrimitiveTopology, VertexFormat},
     },
 };
 
-use super::GIMesh;
-use crate::{error::ConvertError, Vertex};
+use super::{format, to_affine3a, GIMesh, VertexFormats};
+use crate::{error::ConvertError, CustomAttribute, Interpolation, SpatialHash, Vertex};
 
 impl GIMesh {
     /// from [`bevy::prelude::Mesh`] to [`GIMesh`]
+    ///
+    /// NOTE: attributes of any [`VertexAttributeValues`] format are converted,
+    /// their formats are kept in [`GIMesh::formats`].
+    /// Other attributes are added to [`GIMesh::custom_attributes`] with [`Interpolation::Lerp`]
+    ///
+    /// Triangle strips are converted to triangle lists and meshes without indices get an index per vertex,
+    /// see [`GIMesh::from_mesh_welded`] to weld their vertices
+    ///
+    /// `Mesh` doesn't expose the names of its attributes, so custom attributes are named `Vertex_Custom`,
+    /// see [`GIMesh::from_mesh_with_attributes`] to keep them
     pub fn from_mesh(mesh: &Mesh, model: Affine3A) -> Result<Self, ConvertError> {
+        Self::from_mesh_with_attributes(mesh, model, &[])
+    }
+
+    /// Like [`GIMesh::from_mesh`], custom attributes with the id of one of `attributes`
+    /// keep its name and interpolation
+    pub fn from_mesh_with_attributes(
+        mesh: &Mesh,
+        model: Affine3A,
+        attributes: &[CustomAttribute],
+    ) -> Result<Self, ConvertError> {
         let positions = mesh
             .attribute(Mesh::ATTRIBUTE_POSITION)
             .ok_or(ConvertError::NoVertices)?;
         let normals = mesh
             .attribute(Mesh::ATTRIBUTE_NORMAL)
             .ok_or(ConvertError::NoNormals)?;
+        if format::components(positions) < 3 {
+            return Err(ConvertError::VertexInvalidFormat);
+        }
+        if format::components(normals) < 3 {
+            return Err(ConvertError::NormalInvalidFormat);
+        }
+
         let uvs_0 = mesh.attribute(Mesh::ATTRIBUTE_UV_0);
         let uvs_1 = mesh.attribute(Mesh::ATTRIBUTE_UV_1);
         let tangents = mesh.attribute(Mesh::ATTRIBUTE_TANGENT);
@@ -26,49 +53,87 @@ impl GIMesh {
         let joint_weights = mesh.attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT);
         let joint_indices = mesh.attribute(Mesh::ATTRIBUTE_JOINT_INDEX);
 
+        // NOTE: `Mesh` doesn't expose the names of its attributes
+        let (custom_attributes, custom_values): (Vec<CustomAttribute>, Vec<_>) = mesh
+            .attributes()
+            .filter(|(id, _)| !BUILTIN_ATTRIBUTES.contains(id))
+            .map(|(id, values)| {
+                let mut custom = attributes
+                    .iter()
+                    .find(|c| c.attribute.id == id)
+                    .cloned()
+                    .unwrap_or_else(|| CustomAttribute {
+                        attribute: MeshVertexAttribute::new(
+                            "Vertex_Custom",
+                            0,
+                            VertexFormat::Float32,
+                        ),
+                        interpolation: Interpolation::default(),
+                    });
+                custom.attribute.id = id;
+                custom.attribute.format = VertexFormat::from(values);
+
+                (custom, values)
+            })
+            .unzip();
+
+        let defaults = VertexFormats::default();
+        let format_of = |values: Option<&VertexAttributeValues>, default| {
+            values.map_or(default, VertexFormat::from)
+        };
+        let formats = VertexFormats {
+            position: VertexFormat::from(positions),
+            normal: VertexFormat::from(normals),
+            uv0: format_of(uvs_0, defaults.uv0),
+            uv1: format_of(uvs_1, defaults.uv1),
+            tangent: format_of(tangents, defaults.tangent),
+            color: format_of(colors, defaults.color),
+            joint_weight: format_of(joint_weights, defaults.joint_weight),
+            joint_index: format_of(joint_indices, defaults.joint_index),
+        };
+
+        // Strips can be restarted with the largest index
+        let (indices, restart) = match mesh.indices() {
+            Some(Indices::U16(v)) => (v.iter().map(|v| *v as u32).collect(), Some(u16::MAX as u32)),
+            Some(Indices::U32(v)) => (v.clone(), Some(u32::MAX)),
+            None => ((0..positions.len() as u32).collect(), None),
+        };
+        let indices = match mesh.primitive_topology() {
+            PrimitiveTopology::TriangleList => indices,
+            PrimitiveTopology::TriangleStrip => strip_to_list(&indices, restart),
+            topology => return Err(ConvertError::UnsupportedTopology(topology)),
+        };
+
+        let normal_matrix = model.matrix3.inverse().transpose();
         let mut output = Self {
-            indices: match mesh.indices().ok_or(ConvertError::NoIndices)? {
-                Indices::U16(v) => v.iter().map(|v| *v as u32).collect(),
-                Indices::U32(v) => v.clone(),
-            },
+            indices,
             vertices: Vec::with_capacity(positions.len()),
+            groups: Vec::new(),
+            formats,
+            custom_attributes,
+            model: DAffine3::IDENTITY,
             inverse_model: model.inverse(),
         };
 
         for i in 0..output.vertices.capacity() {
             let vertex = Vertex {
-                pos: match positions {
-                    VertexAttributeValues::Float32x3(v) => model.transform_point3a(v[i].into()),
-                    _ => return Err(ConvertError::VertexInvalidFormat),
-                },
-                normal: match normals {
-                    VertexAttributeValues::Float32x3(v) => model.transform_vector3a(v[i].into()),
-                    _ => return Err(ConvertError::NormalInvalidFormat),
-                },
-                uv0: match uvs_0 {
-                    Some(VertexAttributeValues::Float32x2(v)) => Some(v[i].into()),
-                    _ => None,
-                },
-                uv1: match uvs_1 {
-                    Some(VertexAttributeValues::Float32x2(v)) => Some(v[i].into()),
-                    _ => None,
-                },
-                tangent: match tangents {
-                    Some(VertexAttributeValues::Float32x4(v)) => Some(v[i].into()),
-                    _ => None,
-                },
-                color: match colors {
-                    Some(VertexAttributeValues::Float32x4(v)) => Some(v[i].into()),
-                    _ => None,
-                },
-                joint_weight: match joint_weights {
-                    Some(VertexAttributeValues::Float32x4(v)) => Some(v[i].into()),
-                    _ => None,
-                },
-                joint_index: match joint_indices {
-                    Some(VertexAttributeValues::Uint16x4(v)) => Some(v[i].into()),
-                    _ => None,
-                },
+                pos: model.transform_point3a(format::read(positions, i).xyz().into()),
+                normal: (normal_matrix * Vec3A::from(format::read(normals, i).xyz()))
+                    .normalize_or_zero(),
+                uv0: uvs_0.map(|v| format::read(v, i).xy()),
+                uv1: uvs_1.map(|v| format::read(v, i).xy()),
+                tangent: tangents.map(|v| {
+                    let tangent = format::read(v, i);
+                    let xyz = model.transform_vector3a(tangent.xyz().into());
+                    xyz.normalize_or_zero().extend(tangent.w)
+                }),
+                color: colors.map(|v| format::read(v, i)),
+                joint_weight: joint_weights.map(|v| format::read(v, i)),
+                joint_index: joint_indices.map(|v| format::read(v, i).as_u16vec4()),
+                custom: custom_values
+                    .iter()
+                    .map(|values| format::read(values, i))
+                    .collect(),
             };
 
             output.vertices.push(vertex);
@@ -77,8 +142,74 @@ impl GIMesh {
         Ok(output)
     }
 
+    /// Like [`GIMesh::from_mesh`], welding vertices within `weld_distance` of each other with the same attributes,
+    /// e.g. the copies of every corner of a mesh without indices
+    ///
+    /// NOTE: unlike [`GIMesh::merge_vertices`] vertices with different attributes aren't welded,
+    /// so hard edges and UV seams are kept
+    pub fn from_mesh_welded(
+        mesh: &Mesh,
+        model: Affine3A,
+        weld_distance: f32,
+    ) -> Result<Self, ConvertError> {
+        let mut output = Self::from_mesh(mesh, model)?;
+        weld_vertices(&mut output, weld_distance);
+        Ok(output)
+    }
+
+    /// from [`bevy::prelude::Mesh`] to [`GIMesh`], keeping the vertices in the local space of `mesh`
+    ///
+    /// Meshes far from the origin keep their precision, `model` is only used to
+    /// transform other meshes into this space, see [`GIMesh::model`]
+    pub fn from_mesh_local(mesh: &Mesh, model: DAffine3) -> Result<Self, ConvertError> {
+        let mut output = Self::from_mesh(mesh, Affine3A::IDENTITY)?;
+        output.model = model;
+        Ok(output)
+    }
+
+    /// from [`GIMesh`] to one [`bevy::prelude::Mesh`] per group, sorted by group
+    ///
+    /// See [`GIMesh::groups`]
+    pub fn to_meshes(self) -> Result<Vec<(u32, Mesh)>, ConvertError> {
+        self.split_groups()
+            .into_iter()
+            .map(|(group, mesh)| Ok((group, mesh.to_mesh()?)))
+            .collect()
+    }
+
     /// to [`bevy::prelude::Mesh`] to [`GIMesh`]
     pub fn to_mesh(self) -> Result<Mesh, ConvertError> {
+        self.to_mesh_ref()
+    }
+
+    /// Like [`GIMesh::to_mesh`], without consuming `self`
+    pub fn to_mesh_ref(&self) -> Result<Mesh, ConvertError> {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
+        self.write_to_mesh(&mut mesh)?;
+        Ok(mesh)
+    }
+
+    /// to [`bevy::prelude::Mesh`] with the world space positions relative to `origin`, see [`GIMesh::model`]
+    ///
+    /// NOTE: `origin` is usually the translation of the entity the mesh is spawned on, or a floating origin
+    pub fn to_mesh_relative_to(&self, origin: DVec3) -> Result<Mesh, ConvertError> {
+        // Composed in `f64`, so large translations cancel out without losing precision
+        let relative = DAffine3::from_translation(-origin) * self.model;
+
+        // The positions are already relative to `origin`, so `inverse_model` isn't applied
+        let mut mesh = self.clone();
+        mesh.transform(to_affine3a(relative)).inverse_model = Affine3A::IDENTITY;
+        mesh.to_mesh()
+    }
+
+    /// Replaces the indices and vertices of `mesh` with `self`,
+    /// reusing its buffers so e.g. a [`Mesh`] in `Assets<Mesh>` can be updated every frame
+    ///
+    /// NOTE: attributes `self` doesn't have are removed, `mesh` is unchanged if this fails
+    pub fn write_to_mesh(&self, mesh: &mut Mesh) -> Result<(), ConvertError> {
+        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+            return Err(ConvertError::UnsupportedTopology(mesh.primitive_topology()));
+        }
         if self.indices.is_empty() {
             return Err(ConvertError::NoIndices);
         }
@@ -86,99 +217,494 @@ impl GIMesh {
             return Err(ConvertError::NoVertices);
         }
 
-        let indices = Indices::U32(self.indices);
-
-        let vert_count = self.vertices.len();
-        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(vert_count);
-        let mut normals: Vec<[f32; 3]> = Vec::with_capacity(vert_count);
-        let mut uvs0: Option<Vec<[f32; 2]>> = self.vertices[0]
-            .uv0
-            .is_some()
-            .then_some(Vec::with_capacity(vert_count));
-        let mut uvs1: Option<Vec<[f32; 2]>> = self.vertices[0]
-            .uv1
-            .is_some()
-            .then_some(Vec::with_capacity(vert_count));
-        let mut tangents: Option<Vec<[f32; 4]>> = self.vertices[0]
-            .tangent
-            .is_some()
-            .then_some(Vec::with_capacity(vert_count));
-        let mut colors: Option<Vec<[f32; 4]>> = self.vertices[0]
-            .color
-            .is_some()
-            .then_some(Vec::with_capacity(vert_count));
-        let mut joint_weights: Option<Vec<[f32; 4]>> = self.vertices[0]
-            .joint_weight
-            .is_some()
-            .then_some(Vec::with_capacity(vert_count));
-        let mut joint_indices: Option<Vec<[u16; 4]>> = self.vertices[0]
-            .joint_index
-            .is_some()
-            .then_some(Vec::with_capacity(vert_count));
-
-        for v in self.vertices {
-            positions.push(self.inverse_model.transform_point3a(v.pos).into());
-            normals.push(self.inverse_model.transform_vector3a(v.pos).into());
-
-            if let Some(values) = &mut uvs0 {
-                values.push(v.uv0.ok_or(ConvertError::VertexMissingAttribute)?.into());
-            }
-            if let Some(values) = &mut uvs1 {
-                values.push(v.uv1.ok_or(ConvertError::VertexMissingAttribute)?.into());
-            }
-            if let Some(values) = &mut tangents {
-                values.push(
-                    v.tangent
-                        .ok_or(ConvertError::VertexMissingAttribute)?
-                        .into(),
-                );
-            }
-            if let Some(values) = &mut colors {
-                values.push(v.color.ok_or(ConvertError::VertexMissingAttribute)?.into());
-            }
-            if let Some(values) = &mut joint_weights {
-                values.push(
-                    v.joint_weight
-                        .ok_or(ConvertError::VertexMissingAttribute)?
-                        .into(),
-                );
-            }
-            if let Some(values) = &mut joint_indices {
-                values.push(
-                    v.joint_index
-                        .ok_or(ConvertError::VertexMissingAttribute)?
-                        .into(),
-                );
-            }
+        // Attributes of the first vertex are expected on every vertex
+        let first = &self.vertices[0];
+        let missing = self.vertices.iter().any(|v| {
+            (first.uv0.is_some() && v.uv0.is_none())
+                || (first.uv1.is_some() && v.uv1.is_none())
+                || (first.tangent.is_some() && v.tangent.is_none())
+                || (first.color.is_some() && v.color.is_none())
+                || (first.joint_weight.is_some() && v.joint_weight.is_none())
+                || (first.joint_index.is_some() && v.joint_index.is_none())
+        });
+        if missing {
+            return Err(ConvertError::VertexMissingAttribute);
+        }
+
+        match mesh.indices_mut() {
+            Some(Indices::U32(indices)) => indices.clone_from(&self.indices),
+            _ => mesh.insert_indices(Indices::U32(self.indices.clone())),
         }
 
-        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all())
-            .with_inserted_indices(indices);
+        let normal_matrix = self.inverse_model.matrix3.inverse().transpose();
+        let formats = &self.formats;
+        write_attribute(
+            mesh,
+            Mesh::ATTRIBUTE_POSITION,
+            formats.position,
+            self.vertices
+                .iter()
+                .map(|v| self.inverse_model.transform_point3a(v.pos).extend(1.0)),
+        );
+        write_attribute(
+            mesh,
+            Mesh::ATTRIBUTE_NORMAL,
+            formats.normal,
+            self.vertices
+                .iter()
+                .map(|v| (normal_matrix * v.normal).normalize_or_zero().extend(0.0)),
+        );
 
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-        if let Some(values) = uvs0.take() {
-            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, values);
+        if first.uv0.is_some() {
+            let values = self
+                .vertices
+                .iter()
+                .map(|v| v.uv0.unwrap().extend(0.0).extend(1.0));
+            write_attribute(mesh, Mesh::ATTRIBUTE_UV_0, formats.uv0, values);
+        } else {
+            mesh.remove_attribute(Mesh::ATTRIBUTE_UV_0);
         }
-        if let Some(values) = uvs1.take() {
-            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_1, values);
+
+        if first.uv1.is_some() {
+            let values = self
+                .vertices
+                .iter()
+                .map(|v| v.uv1.unwrap().extend(0.0).extend(1.0));
+            write_attribute(mesh, Mesh::ATTRIBUTE_UV_1, formats.uv1, values);
+        } else {
+            mesh.remove_attribute(Mesh::ATTRIBUTE_UV_1);
         }
-        if let Some(values) = tangents.take() {
-            mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, values);
+
+        if first.tangent.is_some() {
+            let values = self.vertices.iter().map(|v| {
+                let tangent = v.tangent.unwrap();
+                let xyz = self
+                    .inverse_model
+                    .transform_vector3a(tangent.xyz().into())
+                    .normalize_or_zero();
+                xyz.extend(tangent.w)
+            });
+            write_attribute(mesh, Mesh::ATTRIBUTE_TANGENT, formats.tangent, values);
+        } else {
+            mesh.remove_attribute(Mesh::ATTRIBUTE_TANGENT);
         }
-        if let Some(values) = colors.take() {
-            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, values);
+
+        if first.color.is_some() {
+            let values = self.vertices.iter().map(|v| v.color.unwrap());
+            write_attribute(mesh, Mesh::ATTRIBUTE_COLOR, formats.color, values);
+        } else {
+            mesh.remove_attribute(Mesh::ATTRIBUTE_COLOR);
         }
-        if let Some(values) = joint_weights.take() {
-            mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, values);
+
+        if first.joint_weight.is_some() {
+            let values = self.vertices.iter().map(|v| v.joint_weight.unwrap());
+            write_attribute(
+                mesh,
+                Mesh::ATTRIBUTE_JOINT_WEIGHT,
+                formats.joint_weight,
+                values,
+            );
+        } else {
+            mesh.remove_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT);
         }
-        if let Some(values) = joint_indices.take() {
-            mesh.insert_attribute(
+
+        if first.joint_index.is_some() {
+            let values = self
+                .vertices
+                .iter()
+                .map(|v| v.joint_index.unwrap().as_vec4());
+            write_attribute(
+                mesh,
                 Mesh::ATTRIBUTE_JOINT_INDEX,
-                VertexAttributeValues::Uint16x4(values),
+                formats.joint_index,
+                values,
             );
+        } else {
+            mesh.remove_attribute(Mesh::ATTRIBUTE_JOINT_INDEX);
         }
 
-        Ok(mesh)
+        for (c, custom) in self.custom_attributes.iter().enumerate() {
+            let values = self
+                .vertices
+                .iter()
+                .map(|v| v.custom.get(c).copied().unwrap_or(Vec4::ZERO));
+            write_attribute(
+                mesh,
+                custom.attribute.clone(),
+                custom.attribute.format,
+                values,
+            );
+        }
+
+        // Attributes `self` doesn't have would have the wrong number of values
+        let stale: Vec<MeshVertexAttributeId> = mesh
+            .attributes()
+            .map(|(id, _)| id)
+            .filter(|id| {
+                !BUILTIN_ATTRIBUTES.contains(id)
+                    && !self.custom_attributes.iter().any(|c| c.attribute.id == *id)
+            })
+            .collect();
+        for id in stale {
+            mesh.remove_attribute(id);
+        }
+
+        Ok(())
+    }
+}
+
+/// Welds the vertices of `mesh` within `distance` of each other with the same attributes,
+/// removing the welded copies
+///
+/// NOTE: indices without a vertex are kept, so [`GIMesh::validate`] still reports them
+fn weld_vertices(mesh: &mut GIMesh, distance: f32) {
+    let dist_sqr = distance * distance;
+    let mut hash = SpatialHash::new(distance);
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(mesh.vertices.len());
+    let mut map = Vec::with_capacity(mesh.vertices.len());
+    for v in mesh.vertices.drain(..) {
+        let existing = hash
+            .nearby(v.pos)
+            .filter(|i| {
+                let other = &vertices[*i as usize];
+                v.pos.distance_squared(other.pos) <= dist_sqr && same_attributes(&v, other)
+            })
+            .min();
+
+        map.push(existing.unwrap_or_else(|| {
+            let i = vertices.len() as u32;
+            hash.insert(v.pos, i);
+            vertices.push(v);
+            i
+        }));
+    }
+
+    mesh.vertices = vertices;
+    for index in &mut mesh.indices {
+        if let Some(welded) = map.get(*index as usize) {
+            *index = *welded;
+        }
+    }
+}
+
+/// Returns `true` if every attribute except the position is equal
+fn same_attributes(a: &Vertex, b: &Vertex) -> bool {
+    a.normal == b.normal
+        && a.uv0 == b.uv0
+        && a.uv1 == b.uv1
+        && a.tangent == b.tangent
+        && a.color == b.color
+        && a.joint_weight == b.joint_weight
+        && a.joint_index == b.joint_index
+        && a.custom == b.custom
+}
+
+/// Converts the indices of a triangle strip to a triangle list, `restart` starts a new strip
+fn strip_to_list(strip: &[u32], restart: Option<u32>) -> Vec<u32> {
+    let mut list = Vec::with_capacity(strip.len().saturating_sub(2) * 3);
+    for part in strip.split(|i| Some(*i) == restart) {
+        for (t, w) in part.windows(3).enumerate() {
+            // Every other triangle is reversed, so they all keep the winding of the first
+            let tri = if t % 2 == 0 {
+                [w[0], w[1], w[2]]
+            } else {
+                [w[1], w[0], w[2]]
+            };
+
+            // Degenerate triangles are used to join strips
+            if tri[0] == tri[1] || tri[1] == tri[2] || tri[2] == tri[0] {
+                continue;
+            }
+
+            list.extend(tri);
+        }
+    }
+
+    list
+}
+
+/// The attributes with a field in [`Vertex`]
+const BUILTIN_ATTRIBUTES: [MeshVertexAttributeId; 8] = [
+    Mesh::ATTRIBUTE_POSITION.id,
+    Mesh::ATTRIBUTE_NORMAL.id,
+    Mesh::ATTRIBUTE_UV_0.id,
+    Mesh::ATTRIBUTE_UV_1.id,
+    Mesh::ATTRIBUTE_TANGENT.id,
+    Mesh::ATTRIBUTE_COLOR.id,
+    Mesh::ATTRIBUTE_JOINT_WEIGHT.id,
+    Mesh::ATTRIBUTE_JOINT_INDEX.id,
+];
+
+/// Replaces the values of `attribute` with `values` in `format`,
+/// reusing its buffer if it already has that format
+///
+/// NOTE: formats that aren't a [`VertexAttributeValues`] variant fall back to the format of `attribute`
+fn write_attribute(
+    mesh: &mut Mesh,
+    attribute: MeshVertexAttribute,
+    format: VertexFormat,
+    values: impl Iterator<Item = Vec4>,
+) {
+    let format = format::empty(format).map_or(attribute.format, |_| format);
+    match mesh.attribute_mut(attribute.id) {
+        Some(existing) if VertexFormat::from(&*existing) == format => {
+            format::write(existing, values);
+        }
+        _ => {
+            let mut new = format::empty(format).unwrap();
+            format::write(&mut new, values);
+            mesh.insert_attribute(
+                MeshVertexAttribute {
+                    format,
+                    ..attribute
+                },
+                new,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        math::{Quat, Vec3},
+        prelude::{Cuboid, Sphere},
+    };
+
+    use super::*;
+    use crate::test_utils::cube;
+
+    #[test]
+    fn unsupported_topologies_are_reported() {
+        let mut lines = Mesh::new(PrimitiveTopology::LineList, RenderAssetUsages::all())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0f32; 3]; 2])
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0f32, 1.0, 0.0]; 2]);
+        assert!(matches!(
+            GIMesh::from_mesh(&lines, Affine3A::IDENTITY),
+            Err(ConvertError::UnsupportedTopology(
+                PrimitiveTopology::LineList
+            ))
+        ));
+
+        assert!(matches!(
+            cube(1.0, Vec3::ZERO).write_to_mesh(&mut lines),
+            Err(ConvertError::UnsupportedTopology(
+                PrimitiveTopology::LineList
+            ))
+        ));
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        use VertexFormat::*;
+        let formats = [
+            Float32, Sint32, Uint32, Float32x2, Sint32x2, Uint32x2, Float32x3, Sint32x3, Uint32x3,
+            Float32x4, Sint32x4, Uint32x4, Sint16x2, Snorm16x2, Uint16x2, Unorm16x2, Sint16x4,
+            Snorm16x4, Uint16x4, Unorm16x4, Sint8x2, Snorm8x2, Uint8x2, Unorm8x2, Sint8x4,
+            Snorm8x4, Uint8x4, Unorm8x4,
+        ];
+
+        let cube = Mesh::from(Cuboid::default());
+        let count = cube.count_vertices();
+        for format in formats {
+            // Values covering negative, fractional and out of range components
+            let mut values = format::empty(format).unwrap();
+            format::write(
+                &mut values,
+                (0..count).map(|i| {
+                    let i = i as f32;
+                    Vec4::new(i % 4.0 - 1.0, 1.0 - i % 3.0 * 0.5, i * 0.25, 300.0 - i)
+                }),
+            );
+
+            let attribute = MeshVertexAttribute::new("Vertex_Test", 1000, format);
+            let mesh = cube
+                .clone()
+                .with_inserted_attribute(attribute.clone(), values.clone());
+            let output = GIMesh::from_mesh(&mesh, Affine3A::IDENTITY)
+                .unwrap()
+                .to_mesh()
+                .unwrap();
+
+            let converted = output.attribute(attribute.id).unwrap();
+            assert_eq!(VertexFormat::from(converted), format);
+            assert_eq!(converted.get_bytes(), values.get_bytes(), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn custom_attribute_names_are_kept() {
+        let attribute = MeshVertexAttribute::new("Vertex_Test", 1000, VertexFormat::Float32);
+        let mesh = Mesh::from(Cuboid::default())
+            .with_inserted_attribute(attribute.clone(), vec![1.0f32; 24]);
+
+        let gimesh = GIMesh::from_mesh(&mesh, Affine3A::IDENTITY).unwrap();
+        assert_eq!(gimesh.custom_attributes[0].attribute.name, "Vertex_Custom");
+
+        let custom = CustomAttribute {
+            attribute,
+            interpolation: Interpolation::Nearest,
+        };
+        let gimesh =
+            GIMesh::from_mesh_with_attributes(&mesh, Affine3A::IDENTITY, &[custom]).unwrap();
+        assert_eq!(gimesh.custom_attributes[0].attribute.name, "Vertex_Test");
+        assert_eq!(
+            gimesh.custom_attributes[0].interpolation,
+            Interpolation::Nearest
+        );
+
+        // The name is only visible in the debug output of `Mesh`
+        let output = gimesh.to_mesh().unwrap();
+        assert!(format!("{output:?}").contains("\"Vertex_Test\""));
+    }
+
+    #[test]
+    fn unindexed_meshes_are_welded() {
+        let mut mesh = Mesh::from(Cuboid::default());
+        mesh.duplicate_vertices();
+        assert!(mesh.indices().is_none());
+
+        let unwelded = GIMesh::from_mesh(&mesh, Affine3A::IDENTITY).unwrap();
+        assert_eq!(unwelded.vertex_count(), 36);
+
+        // The corners of different faces have different normals, so they aren't welded
+        let welded = GIMesh::from_mesh_welded(&mesh, Affine3A::IDENTITY, 1e-4).unwrap();
+        assert_eq!(welded.vertex_count(), 24);
+        assert_eq!(welded.index_count(), 36);
+        assert!(welded.validate().is_valid());
+        for t in 0..welded.tri_count() {
+            for ((a, _), (b, _)) in welded.tri(t).into_iter().zip(unwelded.tri(t)) {
+                assert!(welded.vertex(a) == unwelded.vertex(b));
+            }
+        }
+    }
+
+    #[test]
+    fn welding_keeps_out_of_range_indices() {
+        let mut mesh = Mesh::from(Cuboid::default());
+        let Some(Indices::U32(indices)) = mesh.indices_mut() else {
+            panic!("missing indices");
+        };
+        indices[4] = 999;
+
+        let welded = GIMesh::from_mesh_welded(&mesh, Affine3A::IDENTITY, 1e-4).unwrap();
+        assert_eq!(welded.index(4), 999);
+        assert_eq!(welded.validate().out_of_range_indices, vec![4]);
+    }
+
+    /// A strip of quads along X in the XY plane, facing +Z
+    fn strip(quads: usize, indices: Indices) -> Mesh {
+        let positions: Vec<[f32; 3]> = (0..=quads)
+            .flat_map(|x| [[x as f32, 1.0, 0.0], [x as f32, 0.0, 0.0]])
+            .collect();
+        let normals = vec![[0.0f32, 0.0, 1.0]; positions.len()];
+
+        Mesh::new(PrimitiveTopology::TriangleStrip, RenderAssetUsages::all())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+            .with_inserted_indices(indices)
+    }
+
+    /// Asserts that `mesh` has `count` triangles, all facing +Z
+    fn assert_facing_z(mesh: &GIMesh, count: usize) {
+        assert_eq!(mesh.tri_count(), count);
+        for t in 0..mesh.tri_count() {
+            let [a, b, c] = mesh.tri(t).map(|(i, _)| mesh.vertex(i).pos);
+            assert!((b - a).cross(c - a).z > 0.0, "triangle {t} is flipped");
+        }
+    }
+
+    #[test]
+    fn strips_keep_their_winding() {
+        // Odd and even triangles alternate their winding in the strip
+        let mesh = strip(3, Indices::U32((0..8).collect()));
+        let converted = GIMesh::from_mesh(&mesh, Affine3A::IDENTITY).unwrap();
+        assert_facing_z(&converted, 6);
+
+        // Without indices every vertex is used in order
+        let mut unindexed = mesh.clone();
+        unindexed.remove_indices();
+        let converted = GIMesh::from_mesh(&unindexed, Affine3A::IDENTITY).unwrap();
+        assert_facing_z(&converted, 6);
+    }
+
+    #[test]
+    fn strips_restart_at_the_largest_index() {
+        // Two strips of one quad, every strip starts with an even triangle
+        let mesh = strip(3, Indices::U32(vec![0, 1, 2, 3, u32::MAX, 4, 5, 6, 7]));
+        let converted = GIMesh::from_mesh(&mesh, Affine3A::IDENTITY).unwrap();
+        assert_facing_z(&converted, 4);
+
+        let mesh = strip(3, Indices::U16(vec![0, 1, 2, 3, u16::MAX, 4, 5, 6, 7]));
+        let converted = GIMesh::from_mesh(&mesh, Affine3A::IDENTITY).unwrap();
+        assert_facing_z(&converted, 4);
+
+        // Degenerate triangles joining strips are removed without changing the winding
+        let mesh = strip(3, Indices::U32(vec![0, 1, 2, 3, 3, 4, 4, 5, 6, 7]));
+        let converted = GIMesh::from_mesh(&mesh, Affine3A::IDENTITY).unwrap();
+        assert_facing_z(&converted, 4);
+    }
+
+    #[test]
+    fn far_meshes_come_back_relative_to_the_origin() {
+        let far = DVec3::new(1e7, -3e7, 5e6);
+        let offset = DVec3::new(0.25, 0.125, -0.5);
+        let mesh = GIMesh::from_mesh_local(
+            &Mesh::from(Cuboid::default()),
+            DAffine3::from_translation(far + offset),
+        )
+        .unwrap();
+
+        let output = mesh.to_mesh_relative_to(far).unwrap();
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            output.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("missing positions");
+        };
+
+        let expected = Mesh::from(Cuboid::default());
+        let Some(VertexAttributeValues::Float32x3(expected)) =
+            expected.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("missing positions");
+        };
+
+        for (p, e) in positions.iter().zip(expected) {
+            let e = Vec3::from(*e) + offset.as_vec3();
+            assert!(Vec3::from(*p).abs_diff_eq(e, 1e-6), "{p:?} {e}");
+        }
+    }
+
+    #[test]
+    fn normals_survive_a_non_uniform_scale() {
+        let sphere = Mesh::from(Sphere::new(1.0));
+        let model = Affine3A::from_scale_rotation_translation(
+            Vec3::new(1.0, 3.0, 0.5),
+            Quat::from_rotation_z(0.7),
+            Vec3::new(2.0, -1.0, 4.0),
+        );
+
+        let output = GIMesh::from_mesh(&sphere, model)
+            .unwrap()
+            .to_mesh()
+            .unwrap();
+        let (
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Float32x3(expected)),
+        ) = (
+            output.attribute(Mesh::ATTRIBUTE_NORMAL),
+            sphere.attribute(Mesh::ATTRIBUTE_NORMAL),
+        )
+        else {
+            panic!("missing normals");
+        };
+
+        assert_eq!(normals.len(), expected.len());
+        for (n, e) in normals.iter().zip(expected) {
+            assert!(
+                Vec3::from(*n).abs_diff_eq(Vec3::from(*e), 1e-5),
+                "{n:?} {e:?}"
+            );
+        }
     }
 }
@@ -0,0 +1,195 @@
+use bevy::{
+    math::Vec4,
+    render::{mesh::VertexAttributeValues, render_resource::VertexFormat},
+};
+
+/// The formats of the attributes of the [`bevy::prelude::Mesh`] a [`super::GIMesh`] was created from,
+/// [`super::GIMesh::to_mesh`] converts the attributes back to them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexFormats {
+    pub position: VertexFormat,
+    pub normal: VertexFormat,
+    pub uv0: VertexFormat,
+    pub uv1: VertexFormat,
+    pub tangent: VertexFormat,
+    pub color: VertexFormat,
+    pub joint_weight: VertexFormat,
+    pub joint_index: VertexFormat,
+}
+
+impl Default for VertexFormats {
+    fn default() -> Self {
+        Self {
+            position: VertexFormat::Float32x3,
+            normal: VertexFormat::Float32x3,
+            uv0: VertexFormat::Float32x2,
+            uv1: VertexFormat::Float32x2,
+            tangent: VertexFormat::Float32x4,
+            color: VertexFormat::Float32x4,
+            joint_weight: VertexFormat::Float32x4,
+            joint_index: VertexFormat::Uint16x4,
+        }
+    }
+}
+
+/// Returns the number of components of every value
+pub(crate) fn components(values: &VertexAttributeValues) -> u64 {
+    VertexFormat::from(values).size() / component_size(values)
+}
+
+fn component_size(values: &VertexAttributeValues) -> u64 {
+    use VertexAttributeValues::*;
+    match values {
+        Float32(_) | Sint32(_) | Uint32(_) | Float32x2(_) | Sint32x2(_) | Uint32x2(_)
+        | Float32x3(_) | Sint32x3(_) | Uint32x3(_) | Float32x4(_) | Sint32x4(_) | Uint32x4(_) => 4,
+        Sint16x2(_) | Snorm16x2(_) | Uint16x2(_) | Unorm16x2(_) | Sint16x4(_) | Snorm16x4(_)
+        | Uint16x4(_) | Unorm16x4(_) => 2,
+        Sint8x2(_) | Snorm8x2(_) | Uint8x2(_) | Unorm8x2(_) | Sint8x4(_) | Snorm8x4(_)
+        | Uint8x4(_) | Unorm8x4(_) => 1,
+    }
+}
+
+/// Reads value `i`, normalized formats are mapped to `0..=1` or `-1..=1`
+///
+/// NOTE: missing components are `0`, except a missing fourth component which is `1`
+pub(crate) fn read(values: &VertexAttributeValues, i: usize) -> Vec4 {
+    use VertexAttributeValues::*;
+    match values {
+        Float32(v) => pad(&[v[i]], |c| c),
+        Sint32(v) => pad(&[v[i]], |c| c as f32),
+        Uint32(v) => pad(&[v[i]], |c| c as f32),
+        Float32x2(v) => pad(&v[i], |c| c),
+        Sint32x2(v) => pad(&v[i], |c| c as f32),
+        Uint32x2(v) => pad(&v[i], |c| c as f32),
+        Float32x3(v) => pad(&v[i], |c| c),
+        Sint32x3(v) => pad(&v[i], |c| c as f32),
+        Uint32x3(v) => pad(&v[i], |c| c as f32),
+        Float32x4(v) => pad(&v[i], |c| c),
+        Sint32x4(v) => pad(&v[i], |c| c as f32),
+        Uint32x4(v) => pad(&v[i], |c| c as f32),
+        Sint16x2(v) => pad(&v[i], |c| c as f32),
+        Snorm16x2(v) => pad(&v[i], |c| (c as f32 / i16::MAX as f32).max(-1.0)),
+        Uint16x2(v) => pad(&v[i], |c| c as f32),
+        Unorm16x2(v) => pad(&v[i], |c| c as f32 / u16::MAX as f32),
+        Sint16x4(v) => pad(&v[i], |c| c as f32),
+        Snorm16x4(v) => pad(&v[i], |c| (c as f32 / i16::MAX as f32).max(-1.0)),
+        Uint16x4(v) => pad(&v[i], |c| c as f32),
+        Unorm16x4(v) => pad(&v[i], |c| c as f32 / u16::MAX as f32),
+        Sint8x2(v) => pad(&v[i], |c| c as f32),
+        Snorm8x2(v) => pad(&v[i], |c| (c as f32 / i8::MAX as f32).max(-1.0)),
+        Uint8x2(v) => pad(&v[i], |c| c as f32),
+        Unorm8x2(v) => pad(&v[i], |c| c as f32 / u8::MAX as f32),
+        Sint8x4(v) => pad(&v[i], |c| c as f32),
+        Snorm8x4(v) => pad(&v[i], |c| (c as f32 / i8::MAX as f32).max(-1.0)),
+        Uint8x4(v) => pad(&v[i], |c| c as f32),
+        Unorm8x4(v) => pad(&v[i], |c| c as f32 / u8::MAX as f32),
+    }
+}
+
+fn pad<T: Copy>(components: &[T], f: impl Fn(T) -> f32) -> Vec4 {
+    let mut output = Vec4::new(0.0, 0.0, 0.0, 1.0);
+    for (k, c) in components.iter().enumerate() {
+        output[k] = f(*c);
+    }
+
+    output
+}
+
+/// Returns empty values of `format`, `None` if the format isn't a [`VertexAttributeValues`] variant
+pub(crate) fn empty(format: VertexFormat) -> Option<VertexAttributeValues> {
+    use VertexAttributeValues::*;
+    Some(match format {
+        VertexFormat::Float32 => Float32(Vec::new()),
+        VertexFormat::Sint32 => Sint32(Vec::new()),
+        VertexFormat::Uint32 => Uint32(Vec::new()),
+        VertexFormat::Float32x2 => Float32x2(Vec::new()),
+        VertexFormat::Sint32x2 => Sint32x2(Vec::new()),
+        VertexFormat::Uint32x2 => Uint32x2(Vec::new()),
+        VertexFormat::Float32x3 => Float32x3(Vec::new()),
+        VertexFormat::Sint32x3 => Sint32x3(Vec::new()),
+        VertexFormat::Uint32x3 => Uint32x3(Vec::new()),
+        VertexFormat::Float32x4 => Float32x4(Vec::new()),
+        VertexFormat::Sint32x4 => Sint32x4(Vec::new()),
+        VertexFormat::Uint32x4 => Uint32x4(Vec::new()),
+        VertexFormat::Sint16x2 => Sint16x2(Vec::new()),
+        VertexFormat::Snorm16x2 => Snorm16x2(Vec::new()),
+        VertexFormat::Uint16x2 => Uint16x2(Vec::new()),
+        VertexFormat::Unorm16x2 => Unorm16x2(Vec::new()),
+        VertexFormat::Sint16x4 => Sint16x4(Vec::new()),
+        VertexFormat::Snorm16x4 => Snorm16x4(Vec::new()),
+        VertexFormat::Uint16x4 => Uint16x4(Vec::new()),
+        VertexFormat::Unorm16x4 => Unorm16x4(Vec::new()),
+        VertexFormat::Sint8x2 => Sint8x2(Vec::new()),
+        VertexFormat::Snorm8x2 => Snorm8x2(Vec::new()),
+        VertexFormat::Uint8x2 => Uint8x2(Vec::new()),
+        VertexFormat::Unorm8x2 => Unorm8x2(Vec::new()),
+        VertexFormat::Sint8x4 => Sint8x4(Vec::new()),
+        VertexFormat::Snorm8x4 => Snorm8x4(Vec::new()),
+        VertexFormat::Uint8x4 => Uint8x4(Vec::new()),
+        VertexFormat::Unorm8x4 => Unorm8x4(Vec::new()),
+        _ => return None,
+    })
+}
+
+/// Replaces the contents of `target` with `values`, converted to the format of `target`
+///
+/// NOTE: this is the inverse of [`read`], values out of range of the format are clamped
+pub(crate) fn write(target: &mut VertexAttributeValues, values: impl Iterator<Item = Vec4>) {
+    use VertexAttributeValues::*;
+    match target {
+        Float32(v) => replace_scalar(v, values, |c| c),
+        Sint32(v) => replace_scalar(v, values, |c| c.round() as i32),
+        Uint32(v) => replace_scalar(v, values, |c| c.round() as u32),
+        Float32x2(v) => replace(v, values, |c| c),
+        Sint32x2(v) => replace(v, values, |c| c.round() as i32),
+        Uint32x2(v) => replace(v, values, |c| c.round() as u32),
+        Float32x3(v) => replace(v, values, |c| c),
+        Sint32x3(v) => replace(v, values, |c| c.round() as i32),
+        Uint32x3(v) => replace(v, values, |c| c.round() as u32),
+        Float32x4(v) => replace(v, values, |c| c),
+        Sint32x4(v) => replace(v, values, |c| c.round() as i32),
+        Uint32x4(v) => replace(v, values, |c| c.round() as u32),
+        Sint16x2(v) => replace(v, values, |c| c.round() as i16),
+        Snorm16x2(v) => replace(v, values, |c| snorm(c, i16::MAX as f32) as i16),
+        Uint16x2(v) => replace(v, values, |c| c.round() as u16),
+        Unorm16x2(v) => replace(v, values, |c| unorm(c, u16::MAX as f32) as u16),
+        Sint16x4(v) => replace(v, values, |c| c.round() as i16),
+        Snorm16x4(v) => replace(v, values, |c| snorm(c, i16::MAX as f32) as i16),
+        Uint16x4(v) => replace(v, values, |c| c.round() as u16),
+        Unorm16x4(v) => replace(v, values, |c| unorm(c, u16::MAX as f32) as u16),
+        Sint8x2(v) => replace(v, values, |c| c.round() as i8),
+        Snorm8x2(v) => replace(v, values, |c| snorm(c, i8::MAX as f32) as i8),
+        Uint8x2(v) => replace(v, values, |c| c.round() as u8),
+        Unorm8x2(v) => replace(v, values, |c| unorm(c, u8::MAX as f32) as u8),
+        Sint8x4(v) => replace(v, values, |c| c.round() as i8),
+        Snorm8x4(v) => replace(v, values, |c| snorm(c, i8::MAX as f32) as i8),
+        Uint8x4(v) => replace(v, values, |c| c.round() as u8),
+        Unorm8x4(v) => replace(v, values, |c| unorm(c, u8::MAX as f32) as u8),
+    }
+}
+
+fn replace_scalar<T>(
+    buffer: &mut Vec<T>,
+    values: impl Iterator<Item = Vec4>,
+    f: impl Fn(f32) -> T,
+) {
+    buffer.clear();
+    buffer.extend(values.map(|v| f(v.x)));
+}
+
+fn replace<T, const N: usize>(
+    buffer: &mut Vec<[T; N]>,
+    values: impl Iterator<Item = Vec4>,
+    f: impl Fn(f32) -> T,
+) {
+    buffer.clear();
+    buffer.extend(values.map(|v| std::array::from_fn(|k| f(v[k]))));
+}
+
+fn unorm(c: f32, max: f32) -> f32 {
+    (c.clamp(0.0, 1.0) * max).round()
+}
+
+fn snorm(c: f32, max: f32) -> f32 {
+    (c.clamp(-1.0, 1.0) * max).round()
+}
@@ -1,8 +1,17 @@
 mod conversion;
+mod format;
+mod normals;
 mod ops;
+mod tangents;
 
-use crate::vertex::Vertex;
-use bevy::math::Affine3A;
+pub use format::VertexFormats;
+pub use normals::NormalWeighting;
+
+use crate::{
+    vertex::{CustomAttribute, Vertex},
+    SpatialHash,
+};
+use bevy::math::{Affine3A, DAffine3, Vec3A, Vec4};
 
 /// A Globally-positioned Index Mesh
 #[derive(Clone)]
@@ -13,10 +22,112 @@ pub struct GIMesh {
     /// See [`add_vertex`], [`set_vertex`], [`vertex`] and [`vertex_count`]
     pub vertices: Vec<Vertex>,
 
+    /// The group (e.g. material) of every triangle, see [`set_group`] and [`group`]
+    ///
+    /// NOTE: triangles without an entry are in group `0`
+    pub groups: Vec<u32>,
+
+    /// The attribute formats used by [`GIMesh::to_mesh`]
+    pub formats: VertexFormats,
+
+    /// Attributes without a field in [`Vertex`], see [`Vertex::custom`]
+    pub custom_attributes: Vec<CustomAttribute>,
+
+    /// The transform from the space of the vertices to world space,
+    /// [`DAffine3::IDENTITY`] unless created with [`GIMesh::from_mesh_local`]
+    ///
+    /// Operations transform the other mesh into the space of `self`, see [`GIMesh::in_space_of`].
+    /// It's kept in `f64`, so meshes far from the origin only lose precision to their own extent,
+    /// see [`GIMesh::to_mesh_relative_to`]
+    pub model: DAffine3,
+
     pub inverse_model: Affine3A,
 }
 
+impl Default for GIMesh {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GIMesh {
+    /// Returns an empty [`GIMesh`] in world space with the default [`VertexFormats`]
+    pub fn new() -> Self {
+        Self {
+            indices: Vec::new(),
+            vertices: Vec::new(),
+            groups: Vec::new(),
+            formats: VertexFormats::default(),
+            custom_attributes: Vec::new(),
+            model: DAffine3::IDENTITY,
+            inverse_model: Affine3A::IDENTITY,
+        }
+    }
+
+    /// Returns an empty [`GIMesh`] with the same capacity and `inverse_model` as `self`
+    pub(crate) fn empty_like(&self) -> Self {
+        Self {
+            indices: Vec::with_capacity(self.index_count()),
+            vertices: Vec::with_capacity(self.vertex_count() as usize),
+            groups: Vec::with_capacity(self.groups.len()),
+            formats: self.formats,
+            custom_attributes: self.custom_attributes.clone(),
+            model: self.model,
+            inverse_model: self.inverse_model,
+        }
+    }
+
+    /// Returns a copy of `v`, a vertex of a mesh with the custom attributes `from`,
+    /// with its custom values in the order of [`GIMesh::custom_attributes`]
+    ///
+    /// NOTE: values of attributes `from` doesn't have are zero
+    pub(crate) fn convert_vertex(&self, v: &Vertex, from: &[CustomAttribute]) -> Vertex {
+        let mut v = v.clone();
+        let same = from.len() == self.custom_attributes.len()
+            && from
+                .iter()
+                .zip(&self.custom_attributes)
+                .all(|(a, b)| a.attribute.id == b.attribute.id);
+
+        if !same {
+            v.custom = self
+                .custom_attributes
+                .iter()
+                .map(|c| {
+                    from.iter()
+                        .position(|f| f.attribute.id == c.attribute.id)
+                        .and_then(|i| v.custom.get(i).copied())
+                        .unwrap_or(Vec4::ZERO)
+                })
+                .collect();
+        }
+
+        v
+    }
+
+    /// Returns the id of the position of every vertex and the number of positions,
+    /// vertices within `distance` of each other (e.g. on UV seams or after slicing) share a position
+    pub(crate) fn position_ids(&self, distance: f32) -> (Vec<usize>, usize) {
+        let mut hash = SpatialHash::new(distance);
+        let mut unique: Vec<Vec3A> = Vec::new();
+        let mut ids = Vec::with_capacity(self.vertices.len());
+        for v in &self.vertices {
+            let existing = hash
+                .nearby(v.pos)
+                .filter(|p| v.pos.distance_squared(unique[*p as usize]) <= distance * distance)
+                .min();
+
+            let p = existing.unwrap_or_else(|| {
+                unique.push(v.pos);
+                hash.insert(v.pos, unique.len() as u32 - 1);
+                unique.len() as u32 - 1
+            });
+            ids.push(p as usize);
+        }
+
+        (ids, unique.len())
+    }
+
     pub fn tri_count(&self) -> usize {
         self.indices.len() / 3
     }
@@ -34,6 +145,12 @@ impl GIMesh {
 
 impl GIMesh {
     /// Adds a vertex, unless `v` is within `dist_sqr` of another vertex
+    ///
+    /// NOTE: this compares `v` with every vertex
+    #[deprecated(
+        note = "use [`GIMesh::get_or_add_vertex_hashed`] with [`SpatialHash::from_mesh`]",
+        since = "0.2.0"
+    )]
     pub fn get_or_add_vertex(&mut self, v: Vertex, dist_sqr: f32) -> u32 {
         for i in 0..self.vertex_count() {
             if v.pos.distance_squared(self.vertex(i).pos) <= dist_sqr {
@@ -44,6 +161,28 @@ impl GIMesh {
         self.add_vertex(v)
     }
 
+    /// Adds a vertex, unless `v` is within `dist_sqr` of another vertex in `hash`
+    ///
+    /// NOTE: `hash` must contain every vertex and be created with a distance of at least `dist_sqr.sqrt()`
+    pub fn get_or_add_vertex_hashed(
+        &mut self,
+        v: Vertex,
+        dist_sqr: f32,
+        hash: &mut SpatialHash,
+    ) -> u32 {
+        let existing = hash
+            .nearby(v.pos)
+            .filter(|i| v.pos.distance_squared(self.vertex(*i).pos) <= dist_sqr)
+            .min();
+
+        existing.unwrap_or_else(|| {
+            let pos = v.pos;
+            let i = self.add_vertex(v);
+            hash.insert(pos, i);
+            i
+        })
+    }
+
     /// Adds a vertex to the mesh
     ///
     /// NOTE: Assumes the vertex is unique
@@ -83,4 +222,79 @@ impl GIMesh {
     pub fn index_count(&self) -> usize {
         self.indices.len()
     }
+
+    /// Sets the group of triangle `t`
+    pub fn set_group(&mut self, t: usize, group: u32) {
+        if t >= self.groups.len() {
+            if group == 0 {
+                return;
+            }
+
+            self.groups.resize(t + 1, 0);
+        }
+
+        self.groups[t] = group;
+    }
+
+    /// Sets the group of every triangle
+    pub fn set_groups(&mut self, group: u32) -> &mut Self {
+        self.groups = vec![group; self.tri_count()];
+        self
+    }
+
+    /// Gets the group of triangle `t`
+    pub fn group(&self, t: usize) -> u32 {
+        self.groups.get(t).copied().unwrap_or(0)
+    }
+}
+
+/// Converts `affine` to `f32`
+pub(crate) fn to_affine3a(affine: DAffine3) -> Affine3A {
+    Affine3A {
+        matrix3: affine.matrix3.as_mat3().into(),
+        translation: affine.translation.as_vec3().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3A;
+
+    use crate::{test_utils::cube, SpatialHash, Vertex};
+
+    #[test]
+    #[allow(deprecated)]
+    fn hashed_welding_matches_linear() {
+        let distance = 0.1;
+        let template = cube(1.0, bevy::math::Vec3::ZERO).vertices[0].clone();
+        let vertex = |pos: Vec3A| Vertex {
+            pos,
+            ..template.clone()
+        };
+
+        // Pairs of vertices just either side of a cell boundary, and vertices within a cell
+        let mut positions = Vec::new();
+        for i in 0..20 {
+            let boundary = distance * (i / 2) as f32;
+            let offset = if i % 2 == 0 { -0.03 } else { 0.04 };
+            positions.push(Vec3A::new(boundary + offset, 0.05 * i as f32, boundary));
+            positions.push(Vec3A::new(
+                boundary - offset * 0.5,
+                0.05 * i as f32,
+                boundary,
+            ));
+            positions.push(Vec3A::new(boundary, boundary + offset, 0.3));
+        }
+
+        let mut linear = cube(1.0, bevy::math::Vec3::ZERO);
+        let mut hashed = linear.clone();
+        let mut hash = SpatialHash::from_mesh(&hashed, distance);
+
+        for pos in positions {
+            let a = linear.get_or_add_vertex(vertex(pos), distance * distance);
+            let b = hashed.get_or_add_vertex_hashed(vertex(pos), distance * distance, &mut hash);
+            assert_eq!(a, b, "{pos}");
+        }
+        assert_eq!(linear.vertex_count(), hashed.vertex_count());
+    }
 }
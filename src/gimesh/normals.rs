@@ -0,0 +1,177 @@
+use bevy::{math::Vec3A, utils::HashMap};
+
+use super::GIMesh;
+use crate::DEFAULT_VERTEX_MERGE_DISTANCE;
+
+/// How the faces around a vertex contribute to its smooth normal
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NormalWeighting {
+    /// Faces are weighted by their area
+    Area,
+
+    /// Faces are weighted by their angle at the vertex, independent of how the faces are triangulated
+    #[default]
+    Angle,
+}
+
+impl GIMesh {
+    /// Sets the normal of every vertex to the normal of its face
+    ///
+    /// NOTE: vertices used by several faces are split
+    pub fn flat_normals(&mut self) -> &mut Self {
+        let mut vertices = Vec::with_capacity(self.index_count());
+        for t in 0..self.tri_count() {
+            let tri = self.tri(t);
+            let normal = self.face_normal(t);
+            for (i, index) in tri {
+                let mut v = self.vertex(i).clone();
+                v.normal = normal;
+                vertices.push(v);
+                self.set_index(index, index as u32);
+            }
+        }
+
+        self.vertices = vertices;
+        self
+    }
+
+    /// Sets the normal of every vertex to the weighted average of the faces around its position
+    pub fn smooth_normals(&mut self, weighting: NormalWeighting) -> &mut Self {
+        self.split_normals(std::f32::consts::PI, weighting)
+    }
+
+    /// Like [`GIMesh::smooth_normals`], but faces more than `angle` (in radians) apart don't smooth each other
+    ///
+    /// NOTE: vertices on edges sharper than `angle` are split
+    pub fn split_normals(&mut self, angle: f32, weighting: NormalWeighting) -> &mut Self {
+        let face_normals: Vec<Vec3A> = (0..self.tri_count()).map(|t| self.face_normal(t)).collect();
+        let weights: Vec<[f32; 3]> = (0..self.tri_count())
+            .map(|t| self.corner_weights(t, weighting))
+            .collect();
+
+        // Vertices at the same position (e.g. on UV seams or after slicing) smooth each other
+        let (positions, position_count) = self.position_ids(DEFAULT_VERTEX_MERGE_DISTANCE);
+
+        // The corners (positions in `indices`) at every position
+        let mut corners: Vec<Vec<usize>> = vec![Vec::new(); position_count];
+        for (i, index) in self.indices.iter().enumerate() {
+            corners[positions[*index as usize]].push(i);
+        }
+
+        let cos_angle = angle.cos();
+        let mut split: HashMap<(u32, [u32; 3]), u32> = HashMap::default();
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        for i in 0..self.index_count() {
+            let t = i / 3;
+            let index = self.index(i);
+            let face_normal = face_normals[t];
+
+            let normal = corners[positions[index as usize]]
+                .iter()
+                .filter(|c| face_normals[*c / 3].dot(face_normal) >= cos_angle)
+                .map(|c| face_normals[*c / 3] * weights[*c / 3][*c % 3])
+                .sum::<Vec3A>()
+                .try_normalize()
+                .unwrap_or(face_normal);
+
+            // Corners of the same vertex with the same normal share a vertex
+            let key = (index, normal.to_array().map(f32::to_bits));
+            let new_index = *split.entry(key).or_insert_with(|| {
+                let mut v = self.vertex(index).clone();
+                v.normal = normal;
+                vertices.push(v);
+                vertices.len() as u32 - 1
+            });
+
+            self.set_index(i, new_index);
+        }
+
+        self.vertices = vertices;
+        self
+    }
+
+    /// Returns the normal of triangle `t`, zero if it's degenerate
+    fn face_normal(&self, t: usize) -> Vec3A {
+        let [a, b, c] = self.tri(t).map(|(i, _)| self.vertex(i).pos);
+        (b - a).cross(c - a).normalize_or_zero()
+    }
+
+    /// Returns how much triangle `t` contributes to the normal of each of its corners
+    fn corner_weights(&self, t: usize, weighting: NormalWeighting) -> [f32; 3] {
+        if self.face_normal(t) == Vec3A::ZERO {
+            return [0.0; 3];
+        }
+
+        let p = self.tri(t).map(|(i, _)| self.vertex(i).pos);
+        match weighting {
+            NormalWeighting::Area => [(p[1] - p[0]).cross(p[2] - p[0]).length() * 0.5; 3],
+            NormalWeighting::Angle => [0, 1, 2].map(|k| {
+                let a = p[(k + 1) % 3] - p[k];
+                let b = p[(k + 2) % 3] - p[k];
+                a.angle_between(b)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+    use crate::test_utils::cube;
+
+    #[test]
+    fn split_normals_keep_hard_edges() {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        mesh.smooth_normals(NormalWeighting::Angle);
+        mesh.split_normals(80f32.to_radians(), NormalWeighting::Angle);
+
+        assert_eq!(mesh.vertex_count(), 24);
+        for t in 0..mesh.tri_count() {
+            let face_normal = mesh.face_normal(t);
+            for (i, _) in mesh.tri(t) {
+                assert!(mesh.vertex(i).normal.abs_diff_eq(face_normal, 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    fn angle_weighted_corners_dont_depend_on_triangulation() {
+        let mut flipped = cube(1.0, Vec3::ZERO);
+
+        // Every other face is split along its other diagonal
+        for face in (0..6).step_by(2) {
+            let b = face as u32 * 4;
+            flipped.indices[face * 6..face * 6 + 6].copy_from_slice(&[
+                b + 1,
+                b + 2,
+                b + 3,
+                b + 3,
+                b,
+                b + 1,
+            ]);
+        }
+
+        for mut mesh in [cube(1.0, Vec3::ZERO), flipped] {
+            mesh.smooth_normals(NormalWeighting::Angle);
+            for v in &mesh.vertices {
+                let expected = v.pos.signum() / 3f32.sqrt();
+                assert!(v.normal.abs_diff_eq(expected, 1e-5), "{}", v.normal);
+            }
+        }
+    }
+
+    #[test]
+    fn flat_normals_split_every_corner() {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        mesh.smooth_normals(NormalWeighting::Angle).flat_normals();
+
+        assert_eq!(mesh.vertex_count(), mesh.index_count() as u32);
+        for t in 0..mesh.tri_count() {
+            for (i, _) in mesh.tri(t) {
+                assert_eq!(mesh.vertex(i).normal, mesh.face_normal(t));
+            }
+        }
+    }
+}
@@ -1,17 +1,156 @@
-use super::GIMesh;
+use std::borrow::Cow;
+
+use bevy::{
+    math::{Affine3A, Vec4Swizzles},
+    utils::HashMap,
+};
+
+use super::{to_affine3a, GIMesh};
+use crate::Bvh;
 
 impl GIMesh {
     /// Slices the triangles of `self` by the triangles of `slicer`
     pub fn slice(&mut self, slicer: &Self) -> &mut Self {
-        crate::slice(self, slicer);
-        self
+        self.slice_with(slicer, &crate::SliceSettings::default())
     }
 
     /// Seperates `self` into `inside` and `outside` of `other`
     ///
     /// NOTE: this doesn't slice triangles
     pub fn seperate(&self, other: &Self) -> crate::SeperateOutput {
-        crate::seperate(self, other)
+        self.seperate_with(other, &crate::SeperateSettings::default())
+    }
+
+    /// Slices the triangles of `self` by the triangles of `slicer`
+    pub fn slice_with(&mut self, slicer: &Self, settings: &crate::SliceSettings) -> &mut Self {
+        let slicer = slicer.in_space_of(self);
+        crate::slice::slice_with_bvh(self, &slicer, &slicer.bvh(), settings);
+        self
+    }
+
+    /// Slices the triangles of `self` by the triangles of `slicer`,
+    /// using a prebuilt [`Bvh`] of `slicer`
+    ///
+    /// NOTE: `slicer` must be in the space of `self`, see [`GIMesh::in_space_of`]
+    pub fn slice_with_bvh(
+        &mut self,
+        slicer: &Self,
+        slicer_bvh: &Bvh,
+        settings: &crate::SliceSettings,
+    ) -> &mut Self {
+        crate::slice::slice_with_bvh(self, slicer, slicer_bvh, settings);
+        self
+    }
+
+    /// Seperates `self` into `inside` and `outside` of `other`
+    ///
+    /// NOTE: this doesn't slice triangles
+    pub fn seperate_with(
+        &self,
+        other: &Self,
+        settings: &crate::SeperateSettings,
+    ) -> crate::SeperateOutput {
+        let other = other.in_space_of(self);
+        crate::seperate::seperate_with_bvh(self, &other, &other.bvh(), settings)
+    }
+
+    /// Seperates `self` into `inside` and `outside` of `other`,
+    /// using a prebuilt [`Bvh`] of `other`
+    ///
+    /// NOTE: this doesn't slice triangles and `other` must be in the space of `self`, see [`GIMesh::in_space_of`]
+    pub fn seperate_with_bvh(
+        &self,
+        other: &Self,
+        other_bvh: &Bvh,
+        settings: &crate::SeperateSettings,
+    ) -> crate::SeperateOutput {
+        crate::seperate::seperate_with_bvh(self, other, other_bvh, settings)
+    }
+
+    /// Returns `self` transformed into the space of `other`, see [`GIMesh::model`]
+    pub fn in_space_of(&self, other: &GIMesh) -> Cow<'_, GIMesh> {
+        if self.model == other.model {
+            return Cow::Borrowed(self);
+        }
+
+        // Composed in `f64`, so large translations cancel out without losing precision
+        let relative = other.model.inverse() * self.model;
+
+        let mut mesh = self.clone();
+        mesh.transform(to_affine3a(relative));
+        mesh.model = other.model;
+        Cow::Owned(mesh)
+    }
+
+    /// Checks that `self` is closed, manifold, consistently wound and doesn't intersect itself,
+    /// as expected by [`crate::Boolean`]
+    pub fn validate(&self) -> crate::ValidationReport {
+        crate::validate::validate(self)
+    }
+
+    /// Removes triangles with out of range indices, welds vertices, removes degenerate triangles,
+    /// fixes T-junctions, fills small holes and unifies the winding, so messy inputs can be used by [`crate::Boolean`]
+    ///
+    /// NOTE: the normals of flipped triangles aren't changed, see [`GIMesh::smooth_normals`]
+    pub fn repair(&mut self, settings: &crate::RepairSettings) -> &mut Self {
+        crate::repair::repair(self, settings);
+        self
+    }
+
+    /// Returns the outer shell of `self`, slicing it where it intersects itself
+    /// (e.g. after merging overlapping meshes) and removing the triangles inside of it
+    ///
+    /// NOTE: slices with [`crate::Predicates::Robust`], see [`GIMesh::self_union_with`].
+    /// Identical copies of a triangle facing the same way are kept once,
+    /// coplanar triangles only partially overlapping each other are all kept.
+    /// The pieces on either side of an intersection don't share their vertices, see [`GIMesh::repair`]
+    pub fn self_union(&self) -> GIMesh {
+        self.self_union_with(&crate::SliceSettings {
+            predicates: crate::Predicates::Robust,
+            ..Default::default()
+        })
+    }
+
+    /// Like [`GIMesh::self_union`], slicing with `settings`
+    pub fn self_union_with(&self, settings: &crate::SliceSettings) -> GIMesh {
+        crate::self_union::self_union(self, settings)
+    }
+
+    /// Builds a [`Bvh`] over the triangles of `self`
+    pub fn bvh(&self) -> Bvh {
+        Bvh::new(self)
+    }
+
+    /// Cuts `self` by `plane` into two halves, closing the cut with cap faces
+    pub fn cut_by_plane(&self, plane: crate::CutPlane) -> crate::CutOutput {
+        crate::cut::cut_by_plane(self, plane, &crate::CapSettings::default())
+    }
+
+    /// Cuts `self` by `plane` into two halves, generating the attributes of the cap faces with `settings`
+    pub fn cut_by_plane_with(
+        &self,
+        plane: crate::CutPlane,
+        settings: &crate::CapSettings,
+    ) -> crate::CutOutput {
+        crate::cut::cut_by_plane(self, plane, settings)
+    }
+
+    /// Triangulates the polygon formed by `loops` and adds the triangles to `self`
+    ///
+    /// Loops going counter-clockwise around `normal` are outer boundaries,
+    /// loops going clockwise are holes in the outer boundary containing them.
+    /// The loop vertices are used as is, so their attributes are interpolated across the triangles.
+    /// The triangles are added to `group`
+    ///
+    /// NOTE: the loops are expected to lie on a plane facing `normal` and to not intersect each other
+    pub fn add_polygon(
+        &mut self,
+        loops: &[Vec<crate::Vertex>],
+        normal: bevy::math::Vec3A,
+        group: u32,
+    ) -> &mut Self {
+        crate::triangulate::triangulate(self, loops, normal, group);
+        self
     }
 
     /// Merges `other` into `self`
@@ -25,6 +164,105 @@ impl GIMesh {
         crate::merge_vertices(self, distance)
     }
 
+    /// Transforms the vertices by `transform`
+    ///
+    /// NOTE: `inverse_model` is updated so [`GIMesh::to_mesh`] returns the same local-space mesh
+    pub fn transform(&mut self, transform: Affine3A) -> &mut Self {
+        let normal_matrix = transform.matrix3.inverse().transpose();
+        for v in &mut self.vertices {
+            v.pos = transform.transform_point3a(v.pos);
+            v.normal = (normal_matrix * v.normal).normalize_or_zero();
+            if let Some(tangent) = &mut v.tangent {
+                let xyz = transform
+                    .transform_vector3a(tangent.xyz().into())
+                    .normalize_or_zero();
+                *tangent = xyz.extend(tangent.w);
+            }
+        }
+
+        self.inverse_model *= transform.inverse();
+        self
+    }
+
+    /// Splits `self` into one [`GIMesh`] per group, sorted by group
+    pub fn split_groups(&self) -> Vec<(u32, GIMesh)> {
+        let mut groups: HashMap<u32, (GIMesh, Vec<Option<u32>>)> = HashMap::default();
+        for t in 0..self.tri_count() {
+            let group = self.group(t);
+            let (mesh, map) = groups.entry(group).or_insert_with(|| {
+                let mut mesh = self.empty_like();
+                mesh.groups.clear();
+                (mesh, vec![None; self.vertices.len()])
+            });
+
+            for (i, _) in self.tri(t) {
+                let index =
+                    *map[i as usize].get_or_insert_with(|| mesh.add_vertex(self.vertex(i).clone()));
+                mesh.add_index(index);
+            }
+            mesh.set_group(mesh.tri_count() - 1, group);
+        }
+
+        let mut groups: Vec<(u32, GIMesh)> = groups
+            .into_iter()
+            .map(|(group, (mesh, _))| (group, mesh))
+            .collect();
+        groups.sort_unstable_by_key(|(group, _)| *group);
+        groups
+    }
+
+    /// Splits `self` into one [`GIMesh`] per connected part, in the order of their first triangle
+    ///
+    /// Triangles are connected if they share a vertex, see [`GIMesh::connected_components_with`]
+    pub fn connected_components(&self) -> Vec<GIMesh> {
+        let vertices: Vec<usize> = (0..self.vertices.len()).collect();
+        self.components_of(&vertices, self.vertices.len())
+    }
+
+    /// Like [`GIMesh::connected_components`], but vertices within `weld_distance` of each other
+    /// (e.g. on UV seams) also connect their triangles
+    pub fn connected_components_with(&self, weld_distance: f32) -> Vec<GIMesh> {
+        let (positions, position_count) = self.position_ids(weld_distance);
+        self.components_of(&positions, position_count)
+    }
+
+    /// Splits `self` into its parts, triangles are connected if their corners have the same id in `ids`
+    fn components_of(&self, ids: &[usize], id_count: usize) -> Vec<GIMesh> {
+        let mut parents: Vec<usize> = (0..id_count).collect();
+        for t in 0..self.tri_count() {
+            let [a, b, c] = self
+                .tri(t)
+                .map(|(i, _)| find(&mut parents, ids[i as usize]));
+            parents[b] = a;
+            let c = find(&mut parents, c);
+            parents[c] = a;
+        }
+
+        // Every vertex is only used by the part of its triangles
+        let mut parts = vec![usize::MAX; id_count];
+        let mut map = vec![u32::MAX; self.vertices.len()];
+        let mut output: Vec<GIMesh> = Vec::new();
+        for t in 0..self.tri_count() {
+            let tri = self.tri(t);
+            let root = find(&mut parents, ids[tri[0].0 as usize]);
+            if parts[root] == usize::MAX {
+                parts[root] = output.len();
+                output.push(self.empty_like());
+            }
+
+            let mesh = &mut output[parts[root]];
+            for (i, _) in tri {
+                if map[i as usize] == u32::MAX {
+                    map[i as usize] = mesh.add_vertex(self.vertex(i).clone());
+                }
+                mesh.add_index(map[i as usize]);
+            }
+            mesh.set_group(mesh.tri_count() - 1, self.group(t));
+        }
+
+        output
+    }
+
     /// Inverts the normals
     pub fn invert_normals(&mut self) -> &mut Self {
         for v in &mut self.vertices {
@@ -45,3 +283,37 @@ impl GIMesh {
         self
     }
 }
+
+/// Returns the root of `i` in the disjoint set forest `parents`
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use crate::test_utils::{cube, volume};
+
+    #[test]
+    fn disjoint_cubes_are_two_components() {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        mesh.merge_with(&cube(1.0, Vec3::X * 3.0), &Default::default());
+
+        // The faces of a cube don't share vertices, only their positions
+        let components = mesh.connected_components_with(0.01);
+        assert_eq!(components.len(), 2);
+        for component in components {
+            // Only the vertices of its own triangles are kept
+            let vertices = component.vertex_count();
+            assert!(vertices == 8 || vertices == 24, "{vertices}");
+            assert_eq!(component.tri_count(), 12);
+            assert!((volume(&component) - 1.0).abs() < 1e-5);
+        }
+    }
+}
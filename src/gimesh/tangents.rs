@@ -0,0 +1,86 @@
+use bevy::{math::Vec4, utils::HashMap};
+
+use super::GIMesh;
+use crate::error::GenerateTangentsError;
+
+/// The per-corner data MikkTSpace reads and writes
+struct TangentGeometry<'a> {
+    mesh: &'a GIMesh,
+    tangents: Vec<Vec4>,
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.mesh.tri_count()
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.mesh
+            .vertex(self.mesh.index(face * 3 + vert))
+            .pos
+            .into()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.mesh
+            .vertex(self.mesh.index(face * 3 + vert))
+            .normal
+            .into()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.mesh
+            .vertex(self.mesh.index(face * 3 + vert))
+            .uv0
+            .unwrap_or_default()
+            .into()
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent.into();
+    }
+}
+
+impl GIMesh {
+    /// Generates MikkTSpace tangents from `uv0` and the normals
+    ///
+    /// NOTE: vertices with different tangents for different faces are split,
+    /// the normals should be final (e.g. after [`GIMesh::smooth_normals`])
+    pub fn generate_tangents(&mut self) -> Result<&mut Self, GenerateTangentsError> {
+        if self.vertices.iter().any(|v| v.uv0.is_none()) {
+            return Err(GenerateTangentsError::MissingUv0);
+        }
+
+        let mut geometry = TangentGeometry {
+            mesh: self,
+            tangents: vec![Vec4::ZERO; self.index_count()],
+        };
+        if !bevy_mikktspace::generate_tangents(&mut geometry) {
+            return Err(GenerateTangentsError::MikkTSpace);
+        }
+        let tangents = geometry.tangents;
+
+        // Corners of the same vertex with the same tangent share a vertex
+        let mut split: HashMap<(u32, [u32; 4]), u32> = HashMap::default();
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        for (i, tangent) in tangents.iter().enumerate() {
+            let index = self.index(i);
+            let key = (index, tangent.to_array().map(f32::to_bits));
+            let new_index = *split.entry(key).or_insert_with(|| {
+                let mut v = self.vertex(index).clone();
+                v.tangent = Some(*tangent);
+                vertices.push(v);
+                vertices.len() as u32 - 1
+            });
+
+            self.set_index(i, new_index);
+        }
+
+        self.vertices = vertices;
+        Ok(self)
+    }
+}
@@ -1,20 +1,41 @@
 mod boolean;
+mod bvh;
+mod csg;
+mod cut;
 pub mod error;
 mod gimesh;
 mod merge;
+mod multi_boolean;
+mod predicates;
+mod repair;
+mod self_union;
 mod seperate;
 mod slice;
+mod spatial_hash;
+#[cfg(test)]
+mod test_utils;
+mod triangulate;
+mod validate;
 mod vertex;
 
 pub const DEFAULT_VERTEX_MERGE_DISTANCE: f32 = 0.0001;
 
-pub use gimesh::GIMesh;
-pub use vertex::Vertex;
+pub use gimesh::{GIMesh, NormalWeighting, VertexFormats};
+pub use vertex::{CustomAttribute, Interpolation, Vertex};
 
-pub use boolean::Boolean;
+pub use boolean::{Boolean, Operand, TriangleSource};
+pub use bvh::Bvh;
+pub use csg::{CsgNodeId, CsgOperation, CsgTree};
+pub use cut::{CapSettings, CutOutput, CutPlane};
 
 pub use merge::MergeSettings;
-pub use seperate::SeperateOutput;
+pub use multi_boolean::MultiBoolean;
+pub use predicates::Predicates;
+pub use repair::RepairSettings;
+pub use seperate::{Classification, SeperateOutput, SeperateSettings};
+pub use slice::SliceSettings;
+pub use spatial_hash::SpatialHash;
+pub use validate::ValidationReport;
 
 // ---- Deprecated ----
 #[deprecated(
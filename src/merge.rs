@@ -1,32 +1,30 @@
-use crate::{GIMesh, DEFAULT_VERTEX_MERGE_DISTANCE};
+use crate::{GIMesh, SpatialHash, DEFAULT_VERTEX_MERGE_DISTANCE};
 
 /// Returns a new [`GIMesh`] where every vertex within [`distance`] of another vertex are merged
 pub fn merge_vertices(mesh: &GIMesh, distance: f32) -> GIMesh {
     let dist_sqr = distance * distance;
-    let mut output = GIMesh {
-        indices: Vec::with_capacity(mesh.index_count()),
-        vertices: Vec::with_capacity(mesh.vertex_count() as usize),
-        inverse_model: mesh.inverse_model,
-    };
+    let mut output = mesh.empty_like();
+
+    let mut hash = SpatialHash::new(distance);
 
     for ai in &mesh.indices {
         let v = mesh.vertex(*ai);
-        let mut i = None;
-
-        for bv in 0..output.vertex_count() {
-            if v.pos.distance_squared(output.vertex(bv).pos) < dist_sqr {
-                i = Some(bv);
-                break;
-            }
-        }
-
-        if i.is_none() {
-            i = Some(output.add_vertex(v.clone()));
-        }
 
-        output.add_index(i.unwrap());
+        // The first vertex added within `distance`
+        let i = hash
+            .nearby(v.pos)
+            .filter(|bv| v.pos.distance_squared(output.vertex(*bv).pos) < dist_sqr)
+            .min()
+            .unwrap_or_else(|| {
+                let i = output.add_vertex(v.clone());
+                hash.insert(v.pos, i);
+                i
+            });
+
+        output.add_index(i);
     }
 
+    output.groups.clone_from(&mesh.groups);
     output
 }
 
@@ -48,35 +46,35 @@ impl Default for MergeSettings {
 }
 
 /// Merges `b` into `a`
+///
+/// NOTE: custom attributes of `b` that `a` doesn't have are dropped
 pub fn merge_meshes(a: &mut GIMesh, b: &GIMesh, settings: &MergeSettings) {
+    let b = b.in_space_of(a);
     let distance = settings.merge_distance * settings.merge_distance;
 
+    // Only vertices used by a triangle can be merged with,
+    // preferring the one that's used first in `a.indices`
+    let mut first_use = vec![usize::MAX; a.vertex_count() as usize];
+    let mut hash = SpatialHash::new(settings.merge_distance);
+    for (ai, aindex) in a.indices.iter().enumerate() {
+        if first_use[*aindex as usize] == usize::MAX {
+            first_use[*aindex as usize] = ai;
+            hash.insert(a.vertex(*aindex).pos, *aindex);
+        }
+    }
+
     for t in 0..b.tri_count() {
         let tri = b.tri(t);
         let verts = [b.vertex(tri[0].0), b.vertex(tri[1].0), b.vertex(tri[2].0)];
 
-        let mut ivs = [None, None, None];
-        for ai in 0..a.index_count() {
-            let aindex = a.index(ai);
-            let av = a.vertex(aindex);
-
-            for i in 0..3 {
-                if ivs[i].is_some() {
-                    continue;
-                }
-
-                if verts[i].pos.distance_squared(av.pos) < distance {
-                    ivs[i] = Some(aindex);
-                }
-            }
-
-            if ivs[0].is_some() && ivs[1].is_some() && ivs[2].is_some() {
-                break;
-            }
-        }
+        let ivs = verts.map(|v| {
+            hash.nearby(v.pos)
+                .filter(|aindex| v.pos.distance_squared(a.vertex(*aindex).pos) < distance)
+                .min_by_key(|aindex| first_use[*aindex as usize])
+        });
 
         let i1 = ivs[0].unwrap_or_else(|| {
-            let mut v = verts[0].clone();
+            let mut v = a.convert_vertex(verts[0], &b.custom_attributes);
             if settings.invert_b_normals {
                 v.normal = -v.normal;
             }
@@ -84,7 +82,7 @@ pub fn merge_meshes(a: &mut GIMesh, b: &GIMesh, settings: &MergeSettings) {
             a.add_vertex(v)
         });
         let i2 = ivs[1].unwrap_or_else(|| {
-            let mut v = verts[1].clone();
+            let mut v = a.convert_vertex(verts[1], &b.custom_attributes);
             if settings.invert_b_normals {
                 v.normal = -v.normal;
             }
@@ -92,7 +90,7 @@ pub fn merge_meshes(a: &mut GIMesh, b: &GIMesh, settings: &MergeSettings) {
             a.add_vertex(v)
         });
         let i3 = ivs[2].unwrap_or_else(|| {
-            let mut v = verts[2].clone();
+            let mut v = a.convert_vertex(verts[2], &b.custom_attributes);
             if settings.invert_b_normals {
                 v.normal = -v.normal;
             }
@@ -100,14 +98,22 @@ pub fn merge_meshes(a: &mut GIMesh, b: &GIMesh, settings: &MergeSettings) {
             a.add_vertex(v)
         });
 
-        if settings.invert_b_normals {
-            a.add_index(i3);
-            a.add_index(i2);
-            a.add_index(i1);
+        let new_indices = if settings.invert_b_normals {
+            [i3, i2, i1]
         } else {
-            a.add_index(i1);
-            a.add_index(i2);
-            a.add_index(i3);
+            [i1, i2, i3]
+        };
+
+        first_use.resize(a.vertex_count() as usize, usize::MAX);
+        for index in new_indices {
+            if first_use[index as usize] == usize::MAX {
+                first_use[index as usize] = a.index_count();
+                hash.insert(a.vertex(index).pos, index);
+            }
+
+            a.add_index(index);
         }
+
+        a.set_group(a.tri_count() - 1, b.group(t));
     }
 }
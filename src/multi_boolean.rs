@@ -0,0 +1,183 @@
+use std::borrow::Cow;
+
+use crate::{
+    bvh::{aabb_overlap, tri_aabb},
+    seperate::{classify, Side},
+    Bvh, Classification, GIMesh, Predicates, SeperateSettings, SliceSettings,
+    DEFAULT_VERTEX_MERGE_DISTANCE,
+};
+
+/// Boolean operations over any number of meshes
+///
+/// Every mesh is sliced by the others once and every fragment is classified in a single pass,
+/// which is faster than chaining [`crate::Boolean`] operations
+pub struct MultiBoolean<'a> {
+    pub meshes: &'a [GIMesh],
+    pub vertex_merge_distance: f32,
+
+    /// How triangles are classified as inside or outside of the other meshes
+    pub classification: Classification,
+
+    /// How geometric tests are evaluated while slicing and seperating
+    pub predicates: Predicates,
+
+    /// If `true` faces of different meshes lying in the same plane are detected,
+    /// keeping one copy for `union` and `intersection`
+    pub coplanar: bool,
+}
+
+impl<'a> MultiBoolean<'a> {
+    /// Initializes [`MultiBoolean`] with the same defaults as [`crate::Boolean::new`]
+    pub fn new(meshes: &'a [GIMesh]) -> Self {
+        Self {
+            meshes,
+            vertex_merge_distance: DEFAULT_VERTEX_MERGE_DISTANCE,
+            classification: Classification::default(),
+            predicates: Predicates::default(),
+            coplanar: false,
+        }
+    }
+
+    /// Returns the regions inside of any mesh
+    pub fn union(&self) -> GIMesh {
+        // Only the first copy of a shared face is kept
+        self.combine(|side, i, j| match side {
+            Side::Outside => true,
+            Side::CoplanarSame => i < j,
+            Side::Inside | Side::CoplanarOpposite => false,
+        })
+    }
+
+    /// Returns the regions inside of every mesh
+    pub fn intersection(&self) -> GIMesh {
+        // Only the first copy of a shared face is kept
+        self.combine(|side, i, j| match side {
+            Side::Inside => true,
+            Side::CoplanarSame => i < j,
+            Side::Outside | Side::CoplanarOpposite => false,
+        })
+    }
+
+    /// Slices every mesh by the others and keeps the triangles of mesh `i` where
+    /// `keep(side, i, j)` is `true` for every other mesh `j`
+    fn combine(&self, keep: impl Fn(Side, usize, usize) -> bool) -> GIMesh {
+        let Some(first) = self.meshes.first() else {
+            return GIMesh::new();
+        };
+
+        // Every mesh is transformed into the space of the first one
+        let meshes: Vec<Cow<GIMesh>> = self.meshes.iter().map(|m| m.in_space_of(first)).collect();
+        let bvhs: Vec<Bvh> = meshes.iter().map(|m| Bvh::new(m)).collect();
+        let slice_settings = SliceSettings {
+            predicates: self.predicates,
+            coplanar: self.coplanar,
+        };
+        let seperate_settings = SeperateSettings {
+            classification: self.classification,
+            predicates: self.predicates,
+            coplanar: self.coplanar,
+        };
+
+        let mut output = first.empty_like();
+
+        let mut candidates = Vec::new();
+        for (i, mesh) in meshes.iter().enumerate() {
+            let Some((min, max)) = bvhs[i].bounds() else {
+                continue;
+            };
+
+            // Meshes that can't touch `mesh` don't affect it
+            let others: Vec<bool> = (0..meshes.len())
+                .map(|j| {
+                    j != i
+                        && bvhs[j]
+                            .bounds()
+                            .is_some_and(|(j_min, j_max)| aabb_overlap(min, max, j_min, j_max))
+                })
+                .collect();
+
+            let mut sliced = mesh.clone().into_owned();
+            for j in (0..meshes.len()).filter(|j| others[*j]) {
+                sliced.slice_with_bvh(&meshes[j], &bvhs[j], &slice_settings);
+            }
+
+            for t in 0..sliced.tri_count() {
+                let tri = sliced.tri(t);
+                let verts = tri.map(|(index, _)| sliced.vertex(index));
+                let (t_min, t_max) = tri_aabb(&sliced, t);
+
+                let kept = (0..meshes.len()).filter(|j| *j != i).all(|j| {
+                    let side = if others[j]
+                        && bvhs[j]
+                            .bounds()
+                            .is_some_and(|(j_min, j_max)| aabb_overlap(t_min, t_max, j_min, j_max))
+                    {
+                        classify(
+                            verts,
+                            &meshes[j],
+                            &bvhs[j],
+                            &seperate_settings,
+                            &mut candidates,
+                        )
+                    } else {
+                        Side::Outside
+                    };
+
+                    keep(side, i, j)
+                });
+
+                if kept {
+                    for v in verts {
+                        let v = output.convert_vertex(v, &mesh.custom_attributes);
+                        let index = output.add_vertex(v);
+                        output.add_index(index);
+                    }
+                    output.set_group(output.tri_count() - 1, sliced.group(t));
+                }
+            }
+        }
+
+        output.merge_vertices(self.vertex_merge_distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+    use crate::test_utils::{cube, volume};
+
+    #[test]
+    fn three_overlapping_cubes() {
+        let meshes = [
+            cube(1.0, Vec3::ZERO),
+            cube(1.0, Vec3::new(0.5, 0.25, 0.1)),
+            cube(1.0, Vec3::new(0.25, 0.6, 0.3)),
+        ];
+        let multi = MultiBoolean::new(&meshes);
+
+        // By inclusion-exclusion of the pairwise and triple overlaps
+        assert!((volume(&multi.union()) - 2.2025).abs() < 1e-4);
+        assert!((volume(&multi.intersection()) - 0.14).abs() < 1e-4);
+    }
+
+    #[test]
+    fn flush_cubes_keep_shared_faces_once() {
+        let meshes = [
+            cube(1.0, Vec3::ZERO),
+            cube(1.0, Vec3::X),
+            cube(1.0, Vec3::X * 2.0),
+        ];
+        let mut multi = MultiBoolean::new(&meshes);
+        multi.coplanar = true;
+
+        assert!((volume(&multi.union()) - 3.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn no_meshes_give_an_empty_mesh() {
+        let multi = MultiBoolean::new(&[]);
+        assert_eq!(multi.union().tri_count(), 0);
+    }
+}
@@ -0,0 +1,33 @@
+use bevy::math::{DVec3, Vec3A};
+
+/// How many units of [`f32::EPSILON`] (relative to the size of the coordinates)
+/// a value can be from zero and still be considered zero
+const ROBUST_TOLERANCE: f64 = 8.0;
+
+/// How geometric tests (e.g. "which side of a plane is this vertex on") are evaluated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Predicates {
+    /// `f32` math compared against [`f32::EPSILON`]
+    ///
+    /// NOTE: the tolerance doesn't depend on the size of the mesh,
+    /// large meshes may get sliver triangles and small meshes may fail to split
+    #[default]
+    Fast,
+
+    /// `f64` math compared against a tolerance relative to the size of the coordinates
+    Robust,
+}
+
+/// Converts `v` to a [`DVec3`]
+pub(crate) fn dvec3(v: Vec3A) -> DVec3 {
+    DVec3::new(v.x as f64, v.y as f64, v.z as f64)
+}
+
+/// Returns the distance below which a value computed from `points` is considered zero
+pub(crate) fn tolerance(points: &[DVec3]) -> f64 {
+    let scale = points.iter().fold(f64::MIN_POSITIVE, |scale, p| {
+        scale.max(p.abs().max_element())
+    });
+
+    scale * f32::EPSILON as f64 * ROBUST_TOLERANCE
+}
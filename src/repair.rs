@@ -0,0 +1,533 @@
+use bevy::{math::Vec3A, utils::HashMap};
+
+use crate::{GIMesh, DEFAULT_VERTEX_MERGE_DISTANCE};
+
+/// Which steps of [`GIMesh::repair`] are performed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RepairSettings {
+    /// Vertices closer than this are welded, and vertices closer than this to an edge split it
+    pub weld_distance: f32,
+
+    /// Holes with at most this many edges are filled, `0` doesn't fill holes
+    pub max_hole_edges: usize,
+
+    /// If `true` the triangles of every connected part are wound the same way,
+    /// and closed parts are wound to face outward
+    pub unify_winding: bool,
+}
+
+impl Default for RepairSettings {
+    fn default() -> Self {
+        Self {
+            weld_distance: DEFAULT_VERTEX_MERGE_DISTANCE,
+            max_hole_edges: 32,
+            unify_winding: true,
+        }
+    }
+}
+
+/// Removes triangles with out of range indices, welds vertices, removes degenerate triangles,
+/// fixes T-junctions, fills small holes and unifies the winding
+pub(crate) fn repair(mesh: &mut GIMesh, settings: &RepairSettings) {
+    remove_out_of_range(mesh);
+    weld(mesh, settings.weld_distance);
+    remove_degenerates(mesh);
+    fix_t_junctions(mesh, settings.weld_distance);
+
+    // Holes are only found if the triangles around them are wound the same way,
+    // filling them may close parts so they can be wound to face outward
+    if settings.unify_winding {
+        unify_winding(mesh);
+    }
+
+    if settings.max_hole_edges >= 3 {
+        fill_holes(mesh, settings.max_hole_edges);
+        if settings.unify_winding {
+            unify_winding(mesh);
+        }
+    }
+
+    remove_unused_vertices(mesh);
+}
+
+/// Moves vertices within `distance` of each other to the same position,
+/// vertices that then have the same attributes are merged
+fn weld(mesh: &mut GIMesh, distance: f32) {
+    let (positions, position_count) = mesh.position_ids(distance);
+
+    let mut first: Vec<Option<Vec3A>> = vec![None; position_count];
+    let mut kept: Vec<Vec<u32>> = vec![Vec::new(); position_count];
+    let mut vertices = Vec::with_capacity(mesh.vertices.len());
+    let mut map = Vec::with_capacity(mesh.vertices.len());
+    for (i, v) in mesh.vertices.iter().enumerate() {
+        let p = positions[i];
+        let mut v = v.clone();
+        v.pos = *first[p].get_or_insert(v.pos);
+
+        let index = kept[p]
+            .iter()
+            .copied()
+            .find(|k| vertices[*k as usize] == v)
+            .unwrap_or_else(|| {
+                vertices.push(v);
+                kept[p].push(vertices.len() as u32 - 1);
+                vertices.len() as u32 - 1
+            });
+        map.push(index);
+    }
+
+    for index in &mut mesh.indices {
+        *index = map[*index as usize];
+    }
+    mesh.vertices = vertices;
+}
+
+/// Removes triangles with an index without a vertex
+fn remove_out_of_range(mesh: &mut GIMesh) {
+    let tris: Vec<([u32; 3], u32)> = (0..mesh.tri_count())
+        .map(|t| (mesh.tri(t).map(|(i, _)| i), mesh.group(t)))
+        .filter(|(tri, _)| tri.iter().all(|i| *i < mesh.vertex_count()))
+        .collect();
+
+    set_tris(mesh, tris);
+}
+
+/// Removes triangles with no area
+fn remove_degenerates(mesh: &mut GIMesh) {
+    let tris: Vec<([u32; 3], u32)> = (0..mesh.tri_count())
+        .filter(|t| {
+            let [a, b, c] = mesh.tri(*t).map(|(i, _)| mesh.vertex(i).pos);
+            (b - a).cross(c - a) != Vec3A::ZERO
+        })
+        .map(|t| (mesh.tri(t).map(|(i, _)| i), mesh.group(t)))
+        .collect();
+
+    set_tris(mesh, tris);
+}
+
+/// Splits the edges of triangles that have a vertex of another triangle on them,
+/// so neighbouring triangles share their vertices
+fn fix_t_junctions(mesh: &mut GIMesh, distance: f32) {
+    loop {
+        let (positions, _) = mesh.position_ids(0.0);
+        let edges = edge_uses(mesh, &positions);
+
+        // Only vertices on open edges can be T-junctions, sorted by x to find them quickly
+        let mut open: Vec<(u32, Vec3A)> = Vec::new();
+        for t in 0..mesh.tri_count() {
+            for (k, (i, _)) in mesh.tri(t).into_iter().enumerate() {
+                let j = mesh.tri(t)[(k + 1) % 3].0;
+                if is_open(&edges, positions[i as usize], positions[j as usize]) {
+                    open.push((i, mesh.vertex(i).pos));
+                }
+            }
+        }
+        open.sort_unstable_by(|a, b| a.1.x.total_cmp(&b.1.x));
+
+        let mut tris = Vec::with_capacity(mesh.tri_count());
+        let mut changed = false;
+        for t in 0..mesh.tri_count() {
+            let tri = mesh.tri(t).map(|(i, _)| i);
+            let group = mesh.group(t);
+
+            // The first edge with vertices on it is split, the others are split by the next pass
+            let split = (0..3).find_map(|k| {
+                let (a, b) = (tri[k], tri[(k + 1) % 3]);
+                if !is_open(&edges, positions[a as usize], positions[b as usize]) {
+                    return None;
+                }
+
+                let points = points_on_edge(mesh, &open, tri, k, distance);
+                (!points.is_empty()).then_some((k, points))
+            });
+
+            let Some((k, points)) = split else {
+                tris.push((tri, group));
+                continue;
+            };
+
+            // The triangle is replaced by a fan from the corner opposite the edge
+            let (a, b, c) = (tri[k], tri[(k + 1) % 3], tri[(k + 2) % 3]);
+            let mut previous = a;
+            for (s, pos) in points {
+                let mut v = mesh.vertex(a).clone();
+                v.lerp_with(mesh.vertex(b), s, &mesh.custom_attributes);
+                v.pos = pos;
+                let index = mesh.add_vertex(v);
+
+                tris.push(([previous, index, c], group));
+                previous = index;
+            }
+            tris.push(([previous, b, c], group));
+            changed = true;
+        }
+
+        set_tris(mesh, tris);
+        if !changed {
+            return;
+        }
+    }
+}
+
+/// Returns the interpolation factor and position of every vertex in `open` within `distance` of edge `k` of `tri`,
+/// sorted from its start to its end
+///
+/// NOTE: the corners of `tri` aren't on its edges, splitting a sliver at its own corner would only add degenerate triangles
+fn points_on_edge(
+    mesh: &GIMesh,
+    open: &[(u32, Vec3A)],
+    tri: [u32; 3],
+    k: usize,
+    distance: f32,
+) -> Vec<(f32, Vec3A)> {
+    let corners = tri.map(|i| mesh.vertex(i).pos);
+    let (pa, pb) = (corners[k], corners[(k + 1) % 3]);
+    let edge = pb - pa;
+    let length_squared = edge.length_squared();
+    if length_squared == 0.0 {
+        return Vec::new();
+    }
+
+    let min_x = pa.x.min(pb.x) - distance;
+    let max_x = pa.x.max(pb.x) + distance;
+    let start = open.partition_point(|(_, pos)| pos.x < min_x);
+
+    let mut points: Vec<(f32, Vec3A)> = open[start..]
+        .iter()
+        .take_while(|(_, pos)| pos.x <= max_x)
+        .filter(|(_, pos)| !corners.contains(pos))
+        .filter_map(|(_, pos)| {
+            let s = (*pos - pa).dot(edge) / length_squared;
+            let on_edge =
+                s > 0.0 && s < 1.0 && pos.distance_squared(pa + edge * s) <= distance * distance;
+            on_edge.then_some((s, *pos))
+        })
+        .collect();
+
+    points.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+    points.dedup_by(|a, b| a.1 == b.1);
+    points
+}
+
+/// Fills every hole with at most `max_edges` edges
+fn fill_holes(mesh: &mut GIMesh, max_edges: usize) {
+    let (positions, _) = mesh.position_ids(0.0);
+    let edges = edge_uses(mesh, &positions);
+
+    // Holes are walked against the winding of the triangles around them,
+    // so they're filled with the same winding
+    let mut next: HashMap<usize, Vec<(usize, u32, usize)>> = HashMap::default();
+    for t in 0..mesh.tri_count() {
+        let tri = mesh.tri(t);
+        for k in 0..3 {
+            let (a, b) = (tri[k].0, tri[(k + 1) % 3].0);
+            let (pa, pb) = (positions[a as usize], positions[b as usize]);
+            if is_open(&edges, pa, pb) {
+                next.entry(pb).or_default().push((pa, b, t));
+            }
+        }
+    }
+
+    let mut starts: Vec<usize> = next.keys().copied().collect();
+    starts.sort_unstable();
+    for start in starts {
+        while let Some((mut p, index, t)) = next.get_mut(&start).and_then(Vec::pop) {
+            let mut hole = vec![index];
+            let group = mesh.group(t);
+            let mut closed = p == start;
+            while !closed && hole.len() <= max_edges {
+                let Some((to, index, _)) = next.get_mut(&p).and_then(Vec::pop) else {
+                    break;
+                };
+
+                hole.push(index);
+                p = to;
+                closed = p == start;
+            }
+
+            if !closed || hole.len() > max_edges {
+                continue;
+            }
+
+            let loop_vertices: Vec<_> = hole.iter().map(|i| mesh.vertex(*i).clone()).collect();
+            let normal = newell_normal(loop_vertices.iter().map(|v| v.pos));
+            if normal != Vec3A::ZERO {
+                crate::triangulate::triangulate(mesh, &[loop_vertices], normal, group);
+            }
+        }
+    }
+}
+
+/// Returns the normal of the polygon through `points`, facing the side they go counter-clockwise around
+fn newell_normal(points: impl Iterator<Item = Vec3A> + Clone) -> Vec3A {
+    let next = points.clone().cycle().skip(1);
+    points
+        .zip(next)
+        .map(|(a, b)| a.cross(b))
+        .sum::<Vec3A>()
+        .normalize_or_zero()
+}
+
+/// Flips triangles so every triangle is wound like its neighbours,
+/// closed parts are wound to face outward and open parts like most of their triangles
+fn unify_winding(mesh: &mut GIMesh) {
+    let (positions, _) = mesh.position_ids(0.0);
+
+    // The triangles using every edge and if they use it from the smaller position to the larger one
+    let mut edges: HashMap<(usize, usize), Vec<(usize, bool)>> = HashMap::default();
+    for t in 0..mesh.tri_count() {
+        let tri = mesh.tri(t);
+        for k in 0..3 {
+            let (a, b) = (
+                positions[tri[k].0 as usize],
+                positions[tri[(k + 1) % 3].0 as usize],
+            );
+            edges
+                .entry((a.min(b), a.max(b)))
+                .or_default()
+                .push((t, a < b));
+        }
+    }
+
+    let mut flipped: Vec<Option<bool>> = vec![None; mesh.tri_count()];
+    for seed in 0..mesh.tri_count() {
+        if flipped[seed].is_some() {
+            continue;
+        }
+
+        flipped[seed] = Some(false);
+        let mut part = vec![seed];
+        let mut closed = true;
+        let mut stack = vec![seed];
+        while let Some(t) = stack.pop() {
+            let tri = mesh.tri(t);
+            for k in 0..3 {
+                let (a, b) = (
+                    positions[tri[k].0 as usize],
+                    positions[tri[(k + 1) % 3].0 as usize],
+                );
+                let uses = &edges[&(a.min(b), a.max(b))];
+                if uses.len() != 2 {
+                    closed = false;
+                    continue;
+                }
+
+                let Some((u, u_forward)) = uses.iter().copied().find(|(u, _)| *u != t) else {
+                    continue;
+                };
+
+                if flipped[u].is_none() {
+                    // Neighbours use their shared edge in opposite directions
+                    flipped[u] = Some((flipped[t] == Some(true)) ^ ((a < b) == u_forward));
+                    part.push(u);
+                    stack.push(u);
+                }
+            }
+        }
+
+        // The signed volume of a closed part is negative if it faces inward
+        let volume: f32 = part
+            .iter()
+            .map(|t| {
+                let [a, b, c] = mesh.tri(*t).map(|(i, _)| mesh.vertex(i).pos);
+                let volume = a.dot(b.cross(c));
+                if flipped[*t] == Some(true) {
+                    -volume
+                } else {
+                    volume
+                }
+            })
+            .sum();
+
+        // Open parts keep the winding of most of their triangles
+        let flipped_count = part.iter().filter(|t| flipped[**t] == Some(true)).count();
+        let inward = if closed {
+            volume < 0.0
+        } else {
+            flipped_count * 2 > part.len()
+        };
+
+        if inward {
+            for t in part {
+                flipped[t] = flipped[t].map(|f| !f);
+            }
+        }
+    }
+
+    for (t, flipped) in flipped.into_iter().enumerate() {
+        if flipped == Some(true) {
+            let tri = mesh.tri(t);
+            mesh.set_index(tri[0].1, tri[2].0);
+            mesh.set_index(tri[2].1, tri[0].0);
+        }
+    }
+}
+
+/// Removes vertices that aren't used by a triangle
+fn remove_unused_vertices(mesh: &mut GIMesh) {
+    let mut map = vec![u32::MAX; mesh.vertices.len()];
+    let mut vertices = Vec::with_capacity(mesh.vertices.len());
+    for index in &mut mesh.indices {
+        if map[*index as usize] == u32::MAX {
+            vertices.push(mesh.vertices[*index as usize].clone());
+            map[*index as usize] = vertices.len() as u32 - 1;
+        }
+
+        *index = map[*index as usize];
+    }
+
+    mesh.vertices = vertices;
+}
+
+/// How often every edge between two positions is used in each direction
+fn edge_uses(mesh: &GIMesh, positions: &[usize]) -> HashMap<(usize, usize), u32> {
+    let mut edges = HashMap::default();
+    for t in 0..mesh.tri_count() {
+        let tri = mesh.tri(t);
+        for k in 0..3 {
+            let (a, b) = (
+                positions[tri[k].0 as usize],
+                positions[tri[(k + 1) % 3].0 as usize],
+            );
+            *edges.entry((a, b)).or_default() += 1;
+        }
+    }
+
+    edges
+}
+
+/// Returns `true` if the edge from `a` to `b` is only used by one triangle
+fn is_open(edges: &HashMap<(usize, usize), u32>, a: usize, b: usize) -> bool {
+    let uses = |edge| edges.get(&edge).copied().unwrap_or(0);
+    uses((a, b)) + uses((b, a)) == 1
+}
+
+/// Replaces the triangles of `mesh` with `tris` and their groups
+fn set_tris(mesh: &mut GIMesh, tris: Vec<([u32; 3], u32)>) {
+    mesh.indices.clear();
+    mesh.groups.clear();
+    for (t, (tri, group)) in tris.into_iter().enumerate() {
+        mesh.indices.extend(tri);
+        mesh.set_group(t, group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+    use crate::{
+        test_utils::{cube, volume},
+        Vertex,
+    };
+
+    /// A flat mesh of `tris` facing +Z
+    fn flat(tris: &[[[f32; 2]; 3]]) -> GIMesh {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        let template = mesh.vertices[0].clone();
+        mesh.indices.clear();
+        mesh.vertices.clear();
+        for tri in tris {
+            for [x, y] in tri {
+                let index = mesh.add_vertex(Vertex {
+                    pos: Vec3A::new(*x, *y, 0.0),
+                    normal: Vec3A::Z,
+                    ..template.clone()
+                });
+                mesh.add_index(index);
+            }
+        }
+
+        mesh
+    }
+
+    const NO_HOLES: RepairSettings = RepairSettings {
+        weld_distance: DEFAULT_VERTEX_MERGE_DISTANCE,
+        max_hole_edges: 0,
+        unify_winding: true,
+    };
+
+    #[test]
+    fn t_junctions_are_split() {
+        // The corner at (1, 1) of the right column lies on the edge of the left column
+        let mut mesh = flat(&[
+            [[0.0, 0.0], [1.0, 0.0], [1.0, 2.0]],
+            [[0.0, 0.0], [1.0, 2.0], [0.0, 2.0]],
+            [[1.0, 0.0], [2.0, 0.0], [2.0, 1.0]],
+            [[1.0, 0.0], [2.0, 1.0], [1.0, 1.0]],
+            [[1.0, 1.0], [2.0, 1.0], [2.0, 2.0]],
+            [[1.0, 1.0], [2.0, 2.0], [1.0, 2.0]],
+        ]);
+        assert_eq!(mesh.validate().boundary_edges.len(), 10);
+
+        mesh.repair(&NO_HOLES);
+        let report = mesh.validate();
+        assert_eq!(report.boundary_edges.len(), 7);
+        assert!(report.degenerate_triangles.is_empty());
+        assert!(report.non_manifold_edges.is_empty());
+        assert_eq!(mesh.tri_count(), 7);
+    }
+
+    #[test]
+    fn slivers_are_not_split_at_their_own_corner() {
+        // The third corner is within the weld distance of the opposite edge
+        let mut mesh = flat(&[[[0.0, 0.0], [1.0, 0.0], [0.5, 1e-5]]]);
+        mesh.repair(&NO_HOLES);
+
+        assert_eq!(mesh.tri_count(), 1);
+        assert!(mesh.validate().degenerate_triangles.is_empty());
+    }
+
+    #[test]
+    fn out_of_range_triangles_are_removed() {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        mesh.indices[0] = 999;
+        mesh.repair(&RepairSettings::default());
+
+        // The hole left by the removed triangle is filled again
+        let report = mesh.validate();
+        assert!(report.is_valid(), "{report}");
+        assert_eq!(mesh.tri_count(), 12);
+        assert!((volume(&mesh) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn small_holes_are_filled() {
+        // Removing a face leaves a hole of four edges
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        mesh.indices.drain(0..6);
+
+        let mut unfilled = mesh.clone();
+        unfilled.repair(&RepairSettings {
+            max_hole_edges: 3,
+            ..Default::default()
+        });
+        assert_eq!(unfilled.validate().boundary_edges.len(), 4);
+
+        mesh.repair(&RepairSettings {
+            max_hole_edges: 4,
+            ..Default::default()
+        });
+        let report = mesh.validate();
+        assert!(report.is_valid(), "{report}");
+        assert!((volume(&mesh) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn flipped_triangles_are_unified() {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        mesh.indices.swap(3, 4);
+        assert!(!mesh.validate().is_valid());
+
+        mesh.repair(&RepairSettings::default());
+        let report = mesh.validate();
+        assert!(report.is_valid(), "{report}");
+        assert!((volume(&mesh) - 1.0).abs() < 1e-5);
+
+        // Closed meshes facing inward are turned outward
+        mesh.flip_windings();
+        mesh.repair(&RepairSettings::default());
+        assert!((volume(&mesh) - 1.0).abs() < 1e-5);
+    }
+}
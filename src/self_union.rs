@@ -0,0 +1,117 @@
+use bevy::utils::HashSet;
+
+use crate::{
+    bvh::Bvh, validate::self_intersections, GIMesh, SliceSettings, DEFAULT_VERTEX_MERGE_DISTANCE,
+};
+
+/// How far from a triangle, relative to its longest edge, the sides of it are tested
+const SIDE_OFFSET: f32 = 1e-3;
+
+/// Returns the outer shell of `mesh`, slicing it where it intersects itself
+/// and removing the triangles inside of it
+pub(crate) fn self_union(mesh: &GIMesh, settings: &SliceSettings) -> GIMesh {
+    let (positions, _) = mesh.position_ids(DEFAULT_VERTEX_MERGE_DISTANCE);
+    let pairs = self_intersections(mesh, &positions);
+
+    // Only the intersecting triangles slice the mesh
+    let mut intersecting = vec![false; mesh.tri_count()];
+    for t in pairs.into_iter().flatten() {
+        intersecting[t] = true;
+    }
+
+    let mut slicer = mesh.empty_like();
+    for t in (0..mesh.tri_count()).filter(|t| intersecting[*t]) {
+        for (i, _) in mesh.tri(t) {
+            let index = slicer.add_vertex(mesh.vertex(i).clone());
+            slicer.add_index(index);
+        }
+    }
+
+    let mut sliced = mesh.clone();
+    if slicer.tri_count() > 0 {
+        sliced.slice_with_bvh(&slicer, &slicer.bvh(), settings);
+    }
+
+    // A triangle of the outer shell has nothing in front of it and `mesh` behind it
+    let bvh = Bvh::new(mesh);
+    let (sliced_positions, _) = sliced.position_ids(DEFAULT_VERTEX_MERGE_DISTANCE);
+    let mut kept = HashSet::default();
+    let mut output = mesh.empty_like();
+    for t in 0..sliced.tri_count() {
+        let tri = sliced.tri(t);
+        let [a, b, c] = tri.map(|(i, _)| sliced.vertex(i).pos);
+        let center = (a + b + c) / 3.0;
+        let offset = (b - a).cross(c - a).normalize_or_zero()
+            * a.distance(b).max(b.distance(c)).max(c.distance(a))
+            * SIDE_OFFSET;
+
+        if bvh.winding_number(mesh, center + offset) >= 0.5
+            || bvh.winding_number(mesh, center - offset) < 0.5
+        {
+            continue;
+        }
+
+        // Copies of a triangle (e.g. of a mesh merged twice) all pass the test above, the first one is kept.
+        // They're rotated to start at their smallest position, so only copies facing the same way match
+        let ids = tri.map(|(i, _)| sliced_positions[i as usize]);
+        let first = (0..3).min_by_key(|k| ids[*k]).unwrap();
+        if !kept.insert([0, 1, 2].map(|k| ids[(first + k) % 3])) {
+            continue;
+        }
+
+        for (i, _) in tri {
+            let index = output.add_vertex(sliced.vertex(i).clone());
+            output.add_index(index);
+        }
+        output.set_group(output.tri_count() - 1, sliced.group(t));
+    }
+
+    output.merge_vertices(DEFAULT_VERTEX_MERGE_DISTANCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Vec3, Vec3A};
+
+    use crate::{
+        test_utils::{cube, volume},
+        MergeSettings,
+    };
+
+    #[test]
+    fn overlapping_cubes_lose_their_interior() {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        let other = cube(1.0, Vec3::new(0.5, 0.25, 0.1));
+        mesh.merge_with(&other, &MergeSettings::default());
+
+        let union = mesh.self_union();
+        assert!((volume(&union) - (2.0 - 0.5 * 0.75 * 0.9)).abs() < 1e-4);
+
+        // The pieces on either side of the intersection don't share vertices
+        let mut repaired = union.clone();
+        repaired.repair(&Default::default());
+        let report = repaired.validate();
+        assert!(report.is_valid(), "{report}");
+
+        // Every triangle lies on the surface of one cube and outside of the other
+        let inside = |p: Vec3A, center: Vec3A| ((p - center).abs().max_element()) < 0.5 - 1e-4;
+        for t in 0..union.tri_count() {
+            let [a, b, c] = union.tri(t).map(|(i, _)| union.vertex(i).pos);
+            let center = (a + b + c) / 3.0;
+            assert!(!inside(center, Vec3A::ZERO));
+            assert!(!inside(center, Vec3A::new(0.5, 0.25, 0.1)));
+        }
+    }
+
+    #[test]
+    fn duplicated_shells_are_kept_once() {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        mesh.merge_with(&cube(1.0, Vec3::ZERO), &MergeSettings::default());
+        assert_eq!(mesh.tri_count(), 24);
+
+        let union = mesh.self_union();
+        assert_eq!(union.tri_count(), 12);
+        assert!(union.validate().is_valid());
+        assert!((volume(&union) - 1.0).abs() < 1e-5);
+    }
+}
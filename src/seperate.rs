@@ -1,25 +1,59 @@
 use bevy::math::Vec3A;
 
-use crate::{GIMesh, Vertex};
+use crate::{
+    bvh::Bvh,
+    predicates::{dvec3, tolerance},
+    GIMesh, Predicates, Vertex,
+};
 
 /// Seperates `a` into `inside` and `outside` of `b`
 ///
 /// NOTE: this doesn't slice triangles
 pub fn seperate(a: &GIMesh, b: &GIMesh) -> SeperateOutput {
+    let b = b.in_space_of(a);
+    seperate_with_bvh(a, &b, &Bvh::new(&b), &SeperateSettings::default())
+}
+
+/// Seperates `a` into `inside` and `outside` of `b`
+///
+/// NOTE: `b_bvh` must be built from `b`
+pub(crate) fn seperate_with_bvh(
+    a: &GIMesh,
+    b: &GIMesh,
+    b_bvh: &Bvh,
+    settings: &SeperateSettings,
+) -> SeperateOutput {
+    split_by_sides(a, &seperate_sides(a, b, b_bvh, settings))
+}
+
+/// Returns the [`Side`] of `b` every triangle of `a` is on
+///
+/// NOTE: `b_bvh` must be built from `b`
+pub(crate) fn seperate_sides(
+    a: &GIMesh,
+    b: &GIMesh,
+    b_bvh: &Bvh,
+    settings: &SeperateSettings,
+) -> Vec<Side> {
+    let mut candidates = Vec::new();
+    (0..a.tri_count())
+        .map(|ta| {
+            let verts = a.tri(ta).map(|(i, _)| a.vertex(i));
+            classify(verts, b, b_bvh, settings, &mut candidates)
+        })
+        .collect()
+}
+
+/// Splits `a` by the [`Side`] of every triangle, keeping their order
+pub(crate) fn split_by_sides(a: &GIMesh, sides: &[Side]) -> SeperateOutput {
     let mut output = SeperateOutput {
-        inside: GIMesh {
-            indices: Vec::with_capacity(a.index_count()),
-            vertices: Vec::with_capacity(a.vertex_count() as usize),
-            inverse_model: a.inverse_model,
-        },
-        outside: GIMesh {
-            indices: Vec::with_capacity(a.index_count()),
-            vertices: Vec::with_capacity(a.vertex_count() as usize),
-            inverse_model: a.inverse_model,
-        },
+        inside: a.empty_like(),
+        outside: a.empty_like(),
+        coplanar_same: a.empty_like(),
+        coplanar_opposite: a.empty_like(),
     };
 
-    for ta in 0..a.tri_count() {
+    for (ta, side) in sides.iter().enumerate() {
         let a_tri = a.tri(ta);
         let a_verts = [
             a.vertex(a_tri[0].0),
@@ -27,31 +61,9 @@ pub fn seperate(a: &GIMesh, b: &GIMesh) -> SeperateOutput {
             a.vertex(a_tri[2].0),
         ];
 
-        let a_center = (a_verts[0].pos + a_verts[1].pos + a_verts[2].pos) / 3.0;
-        let a_normal = (a_verts[0].normal + a_verts[1].normal + a_verts[2].normal) / 3.0;
-
-        let mut hits = 0;
-        for tb in 0..b.tri_count() {
-            let b_tri = b.tri(tb);
-            let b_verts = [
-                b.vertex(b_tri[0].0),
-                b.vertex(b_tri[1].0),
-                b.vertex(b_tri[2].0),
-            ];
-
-            if ray_triangle(a_center, a_normal, b_verts) {
-                hits += 1;
-            }
-        }
-
         // Add Triangle to it's respective mesh
-        let mesh = if hits % 2 == 0 {
-            // Outside
-            &mut output.outside
-        } else {
-            // Inside
-            &mut output.inside
-        };
+        let mesh = output.side_mut(*side);
+        let group = a.group(ta);
 
         // FIXME: This will readd vertices already added to `mesh`
         let a = mesh.add_vertex(a_verts[0].clone());
@@ -61,11 +73,163 @@ pub fn seperate(a: &GIMesh, b: &GIMesh) -> SeperateOutput {
         mesh.add_index(a);
         mesh.add_index(b);
         mesh.add_index(c);
+        mesh.set_group(mesh.tri_count() - 1, group);
     }
 
     output
 }
 
+/// Where a triangle is relative to another mesh
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Side {
+    Inside,
+    Outside,
+
+    /// Lying on the other mesh, facing the same way
+    CoplanarSame,
+
+    /// Lying on the other mesh, facing the opposite way
+    CoplanarOpposite,
+}
+
+/// Returns where the triangle `verts` is relative to `b`
+///
+/// NOTE: `b_bvh` must be built from `b`
+pub(crate) fn classify(
+    verts: [&Vertex; 3],
+    b: &GIMesh,
+    b_bvh: &Bvh,
+    settings: &SeperateSettings,
+    candidates: &mut Vec<usize>,
+) -> Side {
+    let center = (verts[0].pos + verts[1].pos + verts[2].pos) / 3.0;
+    let normal = (verts[0].normal + verts[1].normal + verts[2].normal) / 3.0;
+
+    if settings.coplanar {
+        let face = (verts[1].pos - verts[0].pos).cross(verts[2].pos - verts[0].pos);
+        match coplanar_side(center, face, b, b_bvh, candidates) {
+            Some(true) => return Side::CoplanarSame,
+            Some(false) => return Side::CoplanarOpposite,
+            None => {}
+        }
+    }
+
+    if is_inside(center, normal, b, b_bvh, settings, candidates) {
+        Side::Inside
+    } else {
+        Side::Outside
+    }
+}
+
+/// Returns `true` if a triangle at `center` facing `normal` is inside of `b`
+fn is_inside(
+    center: Vec3A,
+    normal: Vec3A,
+    b: &GIMesh,
+    b_bvh: &Bvh,
+    settings: &SeperateSettings,
+    candidates: &mut Vec<usize>,
+) -> bool {
+    match (settings.classification, settings.predicates) {
+        (Classification::RayParity, Predicates::Fast) => {
+            b_bvh.query_ray(center, normal, candidates);
+
+            let mut hits = 0;
+            for &tb in candidates.iter() {
+                let b_tri = b.tri(tb);
+                let b_verts = [
+                    b.vertex(b_tri[0].0),
+                    b.vertex(b_tri[1].0),
+                    b.vertex(b_tri[2].0),
+                ];
+
+                if ray_triangle(center, normal, b_verts) {
+                    hits += 1;
+                }
+            }
+
+            hits % 2 == 1
+        }
+        (Classification::RayParity, Predicates::Robust) => {
+            // Rays through an edge or vertex would hit every triangle around it,
+            // so they're recast in a slightly tilted direction
+            let normal = normal.normalize_or_zero();
+            let (u, v) = normal.any_orthonormal_pair();
+            for k in 0..RECAST_ATTEMPTS {
+                let angle = k as f32 * GOLDEN_ANGLE;
+                let tilt = if k == 0 { 0.0 } else { RECAST_TILT };
+                let direction = normal + (u * angle.cos() + v * angle.sin()) * tilt;
+
+                b_bvh.query_ray(center, direction, candidates);
+                let mut hits = 0;
+                let mut ambiguous = false;
+                for &tb in candidates.iter() {
+                    let b_verts = b.tri(tb).map(|(i, _)| b.vertex(i));
+                    match ray_triangle_robust(center, direction, b_verts) {
+                        RayHit::Hit => hits += 1,
+                        RayHit::Miss => {}
+                        RayHit::Ambiguous => {
+                            ambiguous = true;
+                            break;
+                        }
+                    }
+                }
+
+                if !ambiguous {
+                    return hits % 2 == 1;
+                }
+            }
+
+            // Every ray went through an edge, the winding number doesn't have that problem
+            b_bvh.winding_number(b, center) > 0.5
+        }
+        (Classification::WindingNumber, _) => b_bvh.winding_number(b, center) > 0.5,
+    }
+}
+
+/// How many directions [`Predicates::Robust`] ray parity tries before using the winding number
+const RECAST_ATTEMPTS: usize = 8;
+
+/// How far recast rays are tilted away from the triangle's normal
+const RECAST_TILT: f32 = 0.1;
+
+/// Rotates every recast ray away from the previous ones
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+/// Returns `Some(true)` if `point` lies on a triangle of `b` facing the same way as `face`,
+/// `Some(false)` if it faces the opposite way and `None` if `point` doesn't lie on `b`
+fn coplanar_side(
+    point: Vec3A,
+    face: Vec3A,
+    b: &GIMesh,
+    b_bvh: &Bvh,
+    candidates: &mut Vec<usize>,
+) -> Option<bool> {
+    let p = dvec3(point);
+    let margin = tolerance(&[p]) as f32;
+    b_bvh.query_aabb(point - margin, point + margin, candidates);
+
+    candidates.iter().find_map(|tb| {
+        let b_tri = b.tri(*tb);
+        let corners = b_tri.map(|(i, _)| dvec3(b.vertex(i).pos));
+        let epsilon = tolerance(&[p, corners[0], corners[1], corners[2]]);
+
+        let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        let length = normal.length();
+        if length == 0.0 || normal.dot(p - corners[0]).abs() > epsilon * length {
+            return None;
+        }
+
+        // `point` has to be inside of every edge
+        let inside = (0..3).all(|i| {
+            let edge = corners[(i + 1) % 3] - corners[i];
+            edge.cross(p - corners[i]).dot(normal) >= -epsilon * edge.length() * length
+        });
+
+        inside.then(|| dvec3(face).dot(normal) > 0.0)
+    })
+}
+
 fn ray_triangle(ro: Vec3A, rv: Vec3A, tri: [&Vertex; 3]) -> bool {
     let edge1 = tri[1].pos - tri[0].pos;
     let edge2 = tri[2].pos - tri[0].pos;
@@ -91,10 +255,185 @@ fn ray_triangle(ro: Vec3A, rv: Vec3A, tri: [&Vertex; 3]) -> bool {
     inv_det * edge2.dot(s_cross_e1) > f32::EPSILON
 }
 
+/// How triangles are classified as `inside` or `outside`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Classification {
+    /// Casts a ray along the triangle's normal and counts the triangles it hits
+    ///
+    /// NOTE: Requires a closed mesh, with [`Predicates::Fast`] rays grazing an edge or vertex may be misclassified,
+    /// [`Predicates::Robust`] recasts them
+    #[default]
+    RayParity,
+
+    /// Uses the generalized winding number at the triangle's center,
+    /// works with meshes that have small gaps or overlaps
+    WindingNumber,
+}
+
+pub struct SeperateSettings {
+    /// How triangles are classified as `inside` or `outside`
+    pub classification: Classification,
+
+    /// How ray and triangle intersections are evaluated
+    pub predicates: Predicates,
+
+    /// If `true` triangles lying on `b` are put in `coplanar_same` or `coplanar_opposite`
+    /// instead of `inside` or `outside`
+    pub coplanar: bool,
+}
+
+impl Default for SeperateSettings {
+    fn default() -> Self {
+        Self {
+            classification: Classification::RayParity,
+            predicates: Predicates::Fast,
+            coplanar: false,
+        }
+    }
+}
+
+/// The result of [`ray_triangle_robust`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RayHit {
+    Hit,
+    Miss,
+
+    /// The ray passes within the tolerance of an edge or vertex, or lies in the triangle's plane
+    Ambiguous,
+}
+
+/// [`ray_triangle`] evaluated with `f64` and tolerances relative to the size of the coordinates
+fn ray_triangle_robust(ro: Vec3A, rv: Vec3A, tri: [&Vertex; 3]) -> RayHit {
+    let (ro, rv) = (dvec3(ro), dvec3(rv.normalize_or_zero()));
+    let tri = tri.map(|v| dvec3(v.pos));
+    let epsilon = tolerance(&[ro, tri[0], tri[1], tri[2]]);
+
+    let edge1 = tri[1] - tri[0];
+    let edge2 = tri[2] - tri[0];
+    let normal = edge1.cross(edge2);
+    let area = normal.length();
+    if area <= epsilon * epsilon {
+        return RayHit::Miss;
+    }
+
+    let s = ro - tri[0];
+    let ray_cross_e2 = rv.cross(edge2);
+    let det = edge1.dot(ray_cross_e2);
+
+    // `det` is an area, so it's compared against `epsilon` squared
+    if det.abs() <= epsilon * epsilon {
+        // A ray lying in the plane may run along the triangle
+        return if normal.dot(s).abs() <= epsilon * area {
+            RayHit::Ambiguous
+        } else {
+            RayHit::Miss
+        };
+    }
+
+    let inv_det = 1.0 / det;
+    let t = inv_det * edge2.dot(s.cross(edge1));
+    if t <= epsilon {
+        return RayHit::Miss;
+    }
+
+    // The distance of the hit to every edge, the barycentric coordinates scaled by the heights
+    let u = inv_det * s.dot(ray_cross_e2);
+    let v = inv_det * rv.dot(s.cross(edge1));
+    let distances = [
+        (1.0 - u - v) * area / (tri[2] - tri[1]).length(),
+        u * area / edge2.length(),
+        v * area / edge1.length(),
+    ];
+
+    if distances.iter().any(|d| d.abs() <= epsilon) {
+        RayHit::Ambiguous
+    } else if distances.iter().all(|d| *d > 0.0) {
+        RayHit::Hit
+    } else {
+        RayHit::Miss
+    }
+}
+
 pub struct SeperateOutput {
     #[doc(alias = "intersection")]
     pub inside: GIMesh,
 
     #[doc(alias = "difference")]
     pub outside: GIMesh,
+
+    /// Triangles lying on a triangle of `b` facing the same way,
+    /// only used with [`SeperateSettings::coplanar`]
+    pub coplanar_same: GIMesh,
+
+    /// Triangles lying on a triangle of `b` facing the opposite way,
+    /// only used with [`SeperateSettings::coplanar`]
+    pub coplanar_opposite: GIMesh,
+}
+
+impl SeperateOutput {
+    /// Returns the triangles on `side`
+    pub(crate) fn side(&self, side: Side) -> &GIMesh {
+        match side {
+            Side::Inside => &self.inside,
+            Side::Outside => &self.outside,
+            Side::CoplanarSame => &self.coplanar_same,
+            Side::CoplanarOpposite => &self.coplanar_opposite,
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut GIMesh {
+        match side {
+            Side::Inside => &mut self.inside,
+            Side::Outside => &mut self.outside,
+            Side::CoplanarSame => &mut self.coplanar_same,
+            Side::CoplanarOpposite => &mut self.coplanar_opposite,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+    use crate::test_utils::cube;
+
+    #[test]
+    fn rays_through_edges_are_recast() {
+        let settings = SeperateSettings {
+            predicates: Predicates::Robust,
+            ..Default::default()
+        };
+
+        for offset in [
+            Vec3::ZERO,
+            Vec3::splat(10.0),
+            Vec3::new(1000.0, -250.0, 3.0),
+        ] {
+            let b = cube(1.0, offset);
+            let bvh = b.bvh();
+
+            // A ray from the center along +X goes through the diagonal splitting the face
+            let template = b.vertices[0].clone();
+            let verts = [
+                Vec3A::new(0.0, 0.1, 0.0),
+                Vec3A::new(0.0, -0.05, 0.08),
+                Vec3A::new(0.0, -0.05, -0.08),
+            ]
+            .map(|p| Vertex {
+                pos: p + Vec3A::from(offset),
+                normal: Vec3A::X,
+                ..template.clone()
+            });
+
+            let side = classify(
+                [&verts[0], &verts[1], &verts[2]],
+                &b,
+                &bvh,
+                &settings,
+                &mut Vec::new(),
+            );
+            assert_eq!(side, Side::Inside, "{offset}");
+        }
+    }
 }
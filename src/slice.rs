@@ -1,73 +1,250 @@
-use bevy::{math::Vec3A, prelude::*};
+use bevy::{
+    math::{DVec3, Vec3A},
+    prelude::*,
+};
 
-use crate::{gimesh::GIMesh, vertex::Vertex};
+use crate::{
+    bvh::{aabb_overlap, tri_aabb, Bvh},
+    gimesh::GIMesh,
+    predicates::{dvec3, tolerance},
+    vertex::{CustomAttribute, Vertex},
+    Predicates,
+};
 
 /// Slices `slicee` triangles that are intersecting `slicer` triangles
 pub fn slice(slicee: &mut GIMesh, slicer: &GIMesh) {
-    for slicer_i in 0..slicer.tri_count() {
-        let slicer_indices = slicer.tri(slicer_i);
-        let slicer_verts = [
-            slicer.vertex(slicer_indices[0].0),
-            slicer.vertex(slicer_indices[1].0),
-            slicer.vertex(slicer_indices[2].0),
-        ];
-
-        let plane_normal =
-            (slicer_verts[0].normal + slicer_verts[1].normal + slicer_verts[2].normal) / 3.0;
-        let plane_center = (slicer_verts[0].pos + slicer_verts[1].pos + slicer_verts[2].pos) / 3.0;
-        let plane = Plane::new(plane_normal, -plane_normal.dot(plane_center));
-
-        // Find the minimum and maximum x, y and z values for AABB
-        let slicer_min = slicer_verts[0]
-            .pos
-            .min(slicer_verts[1].pos)
-            .min(slicer_verts[2].pos);
-        let slicer_max = slicer_verts[0]
-            .pos
-            .max(slicer_verts[1].pos)
-            .max(slicer_verts[2].pos);
-
-        for t in 0..slicee.tri_count() {
-            let indices = slicee.tri(t);
-            let verts = [
-                slicee.vertex(indices[0].0),
-                slicee.vertex(indices[1].0),
-                slicee.vertex(indices[2].0),
+    let slicer = slicer.in_space_of(slicee);
+    slice_with_bvh(
+        slicee,
+        &slicer,
+        &Bvh::new(&slicer),
+        &SliceSettings::default(),
+    );
+}
+
+/// Slices `slicee` triangles that are intersecting `slicer` triangles,
+/// returning the triangle of `slicee` every triangle was sliced from
+///
+/// NOTE: `slicer_bvh` must be built from `slicer`
+pub(crate) fn slice_with_bvh(
+    slicee: &mut GIMesh,
+    slicer: &GIMesh,
+    slicer_bvh: &Bvh,
+    settings: &SliceSettings,
+) -> Vec<usize> {
+    let planes: Vec<SlicerPlanes> = (0..slicer.tri_count())
+        .map(|slicer_i| {
+            let slicer_indices = slicer.tri(slicer_i);
+            let slicer_verts = [
+                slicer.vertex(slicer_indices[0].0),
+                slicer.vertex(slicer_indices[1].0),
+                slicer.vertex(slicer_indices[2].0),
             ];
 
-            // Find the minimum and maximum x, y and z values for AABB
-            let min = verts[0].pos.min(verts[1].pos).min(verts[2].pos);
-            let max = verts[0].pos.max(verts[1].pos).max(verts[2].pos);
-
-            // Perform an AABB Check
-            if min.x <= slicer_max.x
-                && max.x >= slicer_min.x
-                && min.y <= slicer_max.y
-                && max.y >= slicer_min.y
-                && min.z <= slicer_max.z
-                && max.z >= slicer_min.z
-            {
-                slice_triangle(&plane, slicee, indices);
+            let face = match settings.predicates {
+                Predicates::Fast => {
+                    let plane_normal =
+                        (slicer_verts[0].normal + slicer_verts[1].normal + slicer_verts[2].normal)
+                            / 3.0;
+                    let plane_center =
+                        (slicer_verts[0].pos + slicer_verts[1].pos + slicer_verts[2].pos) / 3.0;
+                    Plane::new(plane_normal, -plane_normal.dot(plane_center))
+                }
+                Predicates::Robust => Plane::robust(slicer_verts.map(|v| dvec3(v.pos))),
+            };
+
+            SlicerPlanes {
+                face,
+                edges: settings
+                    .coplanar
+                    .then(|| edge_planes(slicer_verts.map(|v| v.pos), settings.predicates)),
+            }
+        })
+        .collect();
+
+    // Pieces are added after every existing triangle
+    let mut parents: Vec<usize> = (0..slicee.tri_count()).collect();
+    let mut candidates = Vec::new();
+    let mut pending = Vec::new();
+    for parent in 0..slicee.tri_count() {
+        let (min, max) = tri_aabb(slicee, parent);
+        slicer_bvh.query_aabb(min, max, &mut candidates);
+
+        // Every candidate is a step for it's face and a step for each of it's edges,
+        // every piece of `t` only has to be sliced by the steps after the one that created it
+        pending.push((parent, 0));
+        while let Some((t, first)) = pending.pop() {
+            for step in first..candidates.len() * 4 {
+                let slicer_i = candidates[step / 4];
+                let slicer_planes = &planes[slicer_i];
+                let plane = match (step % 4, &slicer_planes.edges) {
+                    (0, _) => &slicer_planes.face,
+
+                    // Only pieces lying on the slicer triangle are cut along it's edges
+                    (edge, Some(edges)) if slicer_planes.face.contains(slicee, t) => {
+                        &edges[edge - 1]
+                    }
+                    _ => continue,
+                };
+
+                let (min, max) = tri_aabb(slicee, t);
+                let (slicer_min, slicer_max) = tri_aabb(slicer, slicer_i);
+
+                // Perform an AABB Check
+                if !aabb_overlap(min, max, slicer_min, slicer_max) {
+                    continue;
+                }
+
+                let tri_count = slicee.tri_count();
+                slice_triangle(plane, slicee, slicee.tri(t));
+                pending.extend((tri_count..slicee.tri_count()).map(|piece| (piece, step + 1)));
+                parents.resize(slicee.tri_count(), parent);
             }
         }
     }
+
+    parents
+}
+
+/// Slices every triangle of `mesh` crossing the plane through `point` facing `normal`
+pub(crate) fn slice_by_plane(mesh: &mut GIMesh, point: DVec3, normal: DVec3) {
+    let plane = Plane::Robust {
+        normal: normal.normalize_or_zero(),
+        corners: [point; 3],
+    };
+
+    for t in 0..mesh.tri_count() {
+        slice_triangle(&plane, mesh, mesh.tri(t));
+    }
+}
+
+/// Returns the planes through each edge of a triangle, perpendicular to the triangle
+fn edge_planes(corners: [Vec3A; 3], predicates: Predicates) -> [Plane; 3] {
+    [0, 1, 2].map(|i| {
+        let j = (i + 1) % 3;
+        match predicates {
+            Predicates::Fast => {
+                let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+                let edge_normal = normal.cross(corners[j] - corners[i]).normalize_or_zero();
+                Plane::new(edge_normal, -edge_normal.dot(corners[i]))
+            }
+            Predicates::Robust => {
+                let corners = corners.map(dvec3);
+                let normal = (corners[1] - corners[0])
+                    .cross(corners[2] - corners[0])
+                    .normalize_or_zero();
+                let length = corners[i].distance(corners[j]);
+                Plane::robust([corners[i], corners[j], corners[i] + normal * length])
+            }
+        }
+    })
+}
+
+pub struct SliceSettings {
+    /// How the side of the slicing plane a vertex is on is evaluated
+    pub predicates: Predicates,
+
+    /// If `true` triangles lying on a slicer triangle are also sliced along it's edges,
+    /// so the overlapping part can be seperated
+    pub coplanar: bool,
+}
+
+impl Default for SliceSettings {
+    fn default() -> Self {
+        Self {
+            predicates: Predicates::Fast,
+            coplanar: false,
+        }
+    }
+}
+
+struct SlicerPlanes {
+    face: Plane,
+    edges: Option<[Plane; 3]>,
 }
 
 #[derive(Clone)]
-struct Plane {
-    pub p: Vec4,
-    pub n: Vec3A,
-    pub d: f32,
+enum Plane {
+    /// A plane through the averaged vertex normals, evaluated with `f32`
+    Fast { p: Vec4, n: Vec3A, d: f32 },
+
+    /// A plane through the triangle's corners, evaluated with `f64`
+    Robust { normal: DVec3, corners: [DVec3; 3] },
 }
 
 impl Plane {
     pub fn new(normal: Vec3A, d: f32) -> Self {
-        Self {
+        Self::Fast {
             p: normal.extend(d),
             n: normal,
             d,
         }
     }
+
+    pub fn robust(corners: [DVec3; 3]) -> Self {
+        Self::Robust {
+            normal: (corners[1] - corners[0])
+                .cross(corners[2] - corners[0])
+                .normalize_or_zero(),
+            corners,
+        }
+    }
+
+    /// Returns the signed distance of each position and the distance considered zero
+    fn sides(&self, positions: [Vec3A; 3]) -> ([f64; 3], f64) {
+        match self {
+            Self::Fast { p, .. } => (
+                positions.map(|pos| p.dot(pos.extend(1.0)) as f64),
+                f32::EPSILON as f64,
+            ),
+            Self::Robust { normal, corners } => {
+                let positions = positions.map(dvec3);
+                let epsilon = tolerance(&[
+                    corners[0],
+                    corners[1],
+                    corners[2],
+                    positions[0],
+                    positions[1],
+                    positions[2],
+                ]);
+
+                (positions.map(|pos| normal.dot(pos - corners[0])), epsilon)
+            }
+        }
+    }
+
+    /// Returns `true` if every corner of triangle `t` lies on the plane
+    fn contains(&self, mesh: &GIMesh, t: usize) -> bool {
+        let (sides, epsilon) = self.sides(mesh.tri(t).map(|(i, _)| mesh.vertex(i).pos));
+        sides.iter().all(|side| side.abs() <= epsilon)
+    }
+
+    /// Returns the point where the edge from `vj` to `vi` crosses the plane
+    fn intersect(
+        &self,
+        vi: &Vertex,
+        vj: &Vertex,
+        side_i: f64,
+        side_j: f64,
+        custom: &[CustomAttribute],
+    ) -> Vertex {
+        let mut point = vj.clone();
+        match self {
+            Self::Fast { n, d, .. } => {
+                let s = 1.0 - (d + n.dot(vi.pos)) / n.dot(vi.pos - vj.pos);
+                point.lerp_with(vi, s, custom);
+            }
+            Self::Robust { .. } => {
+                let s = side_j / (side_j - side_i);
+                point.lerp_with(vi, s as f32, custom);
+
+                let (pi, pj) = (dvec3(vi.pos), dvec3(vj.pos));
+                point.pos = (pj + (pi - pj) * s).as_vec3().into();
+            }
+        }
+
+        point
+    }
 }
 
 enum SliceVertex {
@@ -85,14 +262,10 @@ impl SliceVertex {
 }
 
 fn slice_triangle(plane: &Plane, mesh: &mut GIMesh, indices: [(u32, usize); 3]) {
-    let sides = [
-        plane.p.dot(mesh.vertex(indices[0].0).pos.extend(1.0)),
-        plane.p.dot(mesh.vertex(indices[1].0).pos.extend(1.0)),
-        plane.p.dot(mesh.vertex(indices[2].0).pos.extend(1.0)),
-    ];
-
-    if (sides[0] >= -f32::EPSILON && sides[1] >= -f32::EPSILON && sides[2] >= -f32::EPSILON)
-        || (sides[0] <= f32::EPSILON && sides[1] <= f32::EPSILON && sides[2] <= f32::EPSILON)
+    let (sides, epsilon) = plane.sides(indices.map(|(i, _)| mesh.vertex(i).pos));
+
+    if (sides[0] >= -epsilon && sides[1] >= -epsilon && sides[2] >= -epsilon)
+        || (sides[0] <= epsilon && sides[1] <= epsilon && sides[2] <= epsilon)
     {
         return;
     }
@@ -103,23 +276,20 @@ fn slice_triangle(plane: &Plane, mesh: &mut GIMesh, indices: [(u32, usize); 3])
     for i in 0..3 {
         let j = (i + 1) % 3;
 
-        if sides[i] >= -f32::EPSILON {
+        if sides[i] >= -epsilon {
             above.push(SliceVertex::Index(indices[i].0));
         }
 
-        if sides[i] <= f32::EPSILON {
+        if sides[i] <= epsilon {
             below.push(SliceVertex::Index(indices[i].0));
         }
 
-        if (sides[j] >= f32::EPSILON && sides[i] <= -f32::EPSILON)
-            || (sides[i] >= f32::EPSILON && sides[j] <= -f32::EPSILON)
+        if (sides[j] >= epsilon && sides[i] <= -epsilon)
+            || (sides[i] >= epsilon && sides[j] <= -epsilon)
         {
             let vj = mesh.vertex(indices[j].0);
             let vi = mesh.vertex(indices[i].0);
-            let s = 1.0 - (plane.d + plane.n.dot(vi.pos)) / plane.n.dot(vi.pos - vj.pos);
-
-            let mut point = vj.clone();
-            point.lerp(vi, s);
+            let point = plane.intersect(vi, vj, sides[i], sides[j], &mesh.custom_attributes);
 
             above.push(SliceVertex::Vertex(point.clone()));
             below.push(SliceVertex::Vertex(point));
@@ -152,9 +322,12 @@ fn add_vertices_to_mesh(
             mesh.set_index(indices[2].1, index_map[2]);
             *original_used = true;
         } else {
+            // The new triangle is in the group of the one it was sliced from
+            let group = mesh.group(indices[0].1 / 3);
             mesh.add_index(index_map[0]);
             mesh.add_index(index_map[1]);
             mesh.add_index(index_map[2]);
+            mesh.set_group(mesh.tri_count() - 1, group);
         }
     }
 }
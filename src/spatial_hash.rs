@@ -0,0 +1,63 @@
+use bevy::{math::Vec3A, utils::HashMap};
+
+use crate::GIMesh;
+
+/// The smallest cell size, keeps tiny merge distances from creating a cell per vertex
+const MIN_CELL_SIZE: f32 = 1e-6;
+
+/// A grid of vertex indices, used to find vertices within a distance of a position
+///
+/// NOTE: Only vertices within `distance` of each other are guaranteed to be in neighbouring cells
+#[derive(Clone)]
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i64, i64, i64), Vec<u32>>,
+}
+
+impl SpatialHash {
+    /// Creates an empty [`SpatialHash`] for finding vertices within `distance`
+    pub fn new(distance: f32) -> Self {
+        Self {
+            cell_size: distance.max(MIN_CELL_SIZE),
+            cells: HashMap::default(),
+        }
+    }
+
+    /// Creates a [`SpatialHash`] containing every vertex of `mesh`
+    pub fn from_mesh(mesh: &GIMesh, distance: f32) -> Self {
+        let mut hash = Self::new(distance);
+        for (i, v) in mesh.vertices.iter().enumerate() {
+            hash.insert(v.pos, i as u32);
+        }
+
+        hash
+    }
+
+    fn cell(&self, pos: Vec3A) -> (i64, i64, i64) {
+        let cell = (pos / self.cell_size).floor();
+        (cell.x as i64, cell.y as i64, cell.z as i64)
+    }
+
+    /// Adds the vertex `index` at `pos`
+    pub fn insert(&mut self, pos: Vec3A, index: u32) {
+        self.cells.entry(self.cell(pos)).or_default().push(index);
+    }
+
+    /// Returns every vertex index in the cells neighbouring `pos`
+    ///
+    /// NOTE: The indices aren't sorted and may be further than `distance` from `pos`
+    pub fn nearby(&self, pos: Vec3A) -> impl Iterator<Item = u32> + '_ {
+        let (x, y, z) = self.cell(pos);
+        (-1..=1)
+            .flat_map(move |dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| (dx, dy, dz))))
+            .filter_map(move |(dx, dy, dz)| {
+                self.cells.get(&(
+                    x.saturating_add(dx),
+                    y.saturating_add(dy),
+                    z.saturating_add(dz),
+                ))
+            })
+            .flatten()
+            .copied()
+    }
+}
@@ -0,0 +1,33 @@
+use bevy::{math::Affine3A, prelude::*};
+
+use crate::GIMesh;
+
+/// A cube of `size` centered on `center`
+pub(crate) fn cube(size: f32, center: Vec3) -> GIMesh {
+    GIMesh::from_mesh(
+        &Mesh::from(Cuboid::from_size(Vec3::splat(size))),
+        Affine3A::from_translation(center),
+    )
+    .unwrap()
+}
+
+/// A sphere of `radius` around the origin
+pub(crate) fn sphere(radius: f32) -> GIMesh {
+    GIMesh::from_mesh(&Mesh::from(Sphere::new(radius)), Affine3A::IDENTITY).unwrap()
+}
+
+/// The signed volume enclosed by `mesh`, positive if it faces outwards
+///
+/// NOTE: measured from the first vertex, so far away meshes don't lose precision
+pub(crate) fn volume(mesh: &GIMesh) -> f32 {
+    let Some(origin) = mesh.vertices.first().map(|v| v.pos) else {
+        return 0.0;
+    };
+
+    (0..mesh.tri_count())
+        .map(|t| {
+            let [a, b, c] = mesh.tri(t).map(|(i, _)| mesh.vertex(i).pos - origin);
+            a.dot(b.cross(c)) / 6.0
+        })
+        .sum()
+}
@@ -0,0 +1,312 @@
+use bevy::math::{Vec2, Vec3A};
+
+use crate::{GIMesh, Vertex};
+
+/// Triangulates the polygon formed by `loops` and adds the triangles to `mesh`, see [`GIMesh::add_polygon`]
+pub(crate) fn triangulate(mesh: &mut GIMesh, loops: &[Vec<Vertex>], normal: Vec3A, group: u32) {
+    let u = normal.any_orthonormal_vector();
+    let v = normal.cross(u);
+
+    let mut points = Vec::new();
+    let mut polygons = Vec::new();
+    for vertices in loops {
+        let start = points.len();
+        points.extend(
+            vertices
+                .iter()
+                .map(|vertex| Vec2::new(vertex.pos.dot(u), vertex.pos.dot(v))),
+        );
+        polygons.push((start..points.len()).collect::<Vec<usize>>());
+    }
+
+    let triangles = triangulate_2d(&points, &polygons);
+
+    let first = mesh.vertex_count();
+    for vertex in loops.iter().flatten() {
+        mesh.add_vertex(vertex.clone());
+    }
+
+    for tri in triangles {
+        for i in tri {
+            mesh.add_index(first + i as u32);
+        }
+        mesh.set_group(mesh.tri_count() - 1, group);
+    }
+}
+
+/// Triangulates the polygons formed by `loops`, each loop is a list of indices into `points`
+///
+/// Counter-clockwise loops are outer boundaries, clockwise loops are holes
+fn triangulate_2d(points: &[Vec2], loops: &[Vec<usize>]) -> Vec<[usize; 3]> {
+    let areas: Vec<f32> = loops.iter().map(|l| signed_area(points, l)).collect();
+
+    // Every hole belongs to the smallest outer boundary containing it
+    let mut holes: Vec<Vec<usize>> = vec![Vec::new(); loops.len()];
+    for (h, hole) in loops.iter().enumerate() {
+        if areas[h] >= 0.0 || hole.is_empty() {
+            continue;
+        }
+
+        let outer = (0..loops.len())
+            .filter(|o| areas[*o] > 0.0 && contains(points, &loops[*o], points[hole[0]]))
+            .min_by(|a, b| areas[*a].total_cmp(&areas[*b]));
+
+        if let Some(outer) = outer {
+            holes[outer].push(h);
+        }
+    }
+
+    let mut triangles = Vec::new();
+    for (o, outer) in loops.iter().enumerate() {
+        if areas[o] <= 0.0 {
+            continue;
+        }
+
+        // Holes are joined to the outer boundary from right to left, so the bridges don't cross
+        let mut polygon = outer.clone();
+        holes[o].sort_by(|a, b| max_x(points, &loops[*b]).total_cmp(&max_x(points, &loops[*a])));
+        for h in &holes[o] {
+            bridge_hole(points, &mut polygon, &loops[*h]);
+        }
+
+        triangles.extend(ear_clip(points, &polygon));
+    }
+
+    triangles
+}
+
+/// Returns twice the signed area of the polygon, positive if it's counter-clockwise
+fn signed_area(points: &[Vec2], polygon: &[usize]) -> f32 {
+    (0..polygon.len())
+        .map(|i| points[polygon[i]].perp_dot(points[polygon[(i + 1) % polygon.len()]]))
+        .sum()
+}
+
+fn max_x(points: &[Vec2], polygon: &[usize]) -> f32 {
+    polygon
+        .iter()
+        .map(|p| points[*p].x)
+        .fold(f32::NEG_INFINITY, f32::max)
+}
+
+/// Returns `true` if `p` is inside of the polygon
+fn contains(points: &[Vec2], polygon: &[usize], p: Vec2) -> bool {
+    let mut inside = false;
+    for i in 0..polygon.len() {
+        let a = points[polygon[i]];
+        let b = points[polygon[(i + 1) % polygon.len()]];
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+/// Joins `hole` to `polygon` with a pair of edges from the hole's rightmost point
+/// to a point of `polygon` visible from it
+fn bridge_hole(points: &[Vec2], polygon: &mut Vec<usize>, hole: &[usize]) {
+    let m = (0..hole.len())
+        .max_by(|a, b| {
+            let (a, b) = (points[hole[*a]], points[hole[*b]]);
+            a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y))
+        })
+        .unwrap();
+    let mp = points[hole[m]];
+
+    // Find the closest edge to the right of `mp`
+    let mut closest: Option<(f32, usize)> = None;
+    for i in 0..polygon.len() {
+        let a = points[polygon[i]];
+        let b = points[polygon[(i + 1) % polygon.len()]];
+        if (a.y > mp.y) == (b.y > mp.y) && a.y != mp.y && b.y != mp.y {
+            continue;
+        }
+
+        let x = if a.y == b.y {
+            a.x.min(b.x)
+        } else {
+            a.x + (mp.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        };
+
+        if x >= mp.x && closest.map_or(true, |(closest_x, _)| x < closest_x) {
+            // The rightmost end of the edge is a candidate
+            let end = if a.x > b.x {
+                i
+            } else {
+                (i + 1) % polygon.len()
+            };
+            closest = Some((x, end));
+        }
+    }
+
+    let p = match closest {
+        Some((x, end)) => {
+            // A vertex inside of the triangle `mp`, `hit`, `end` would block the bridge,
+            // the one closest in angle to the ray is visible
+            let hit = Vec2::new(x, mp.y);
+            let tri = [mp, hit, points[polygon[end]]];
+            let tri = if (tri[1] - tri[0]).perp_dot(tri[2] - tri[0]) < 0.0 {
+                [tri[0], tri[2], tri[1]]
+            } else {
+                tri
+            };
+
+            (0..polygon.len())
+                .filter(|i| *i != end && points[polygon[*i]] != points[polygon[end]])
+                .filter(|i| in_triangle(points[polygon[*i]], tri))
+                .max_by(|a, b| {
+                    let angle = |i: usize| {
+                        let d = points[polygon[i]] - mp;
+                        d.x / d.length()
+                    };
+                    angle(*a).total_cmp(&angle(*b))
+                })
+                .unwrap_or(end)
+        }
+
+        // Degenerate input, the closest point is used
+        None => (0..polygon.len())
+            .min_by(|a, b| {
+                points[polygon[*a]]
+                    .distance_squared(mp)
+                    .total_cmp(&points[polygon[*b]].distance_squared(mp))
+            })
+            .unwrap(),
+    };
+
+    let mut bridged = Vec::with_capacity(polygon.len() + hole.len() + 2);
+    bridged.extend_from_slice(&polygon[..=p]);
+    bridged.extend((0..=hole.len()).map(|i| hole[(m + i) % hole.len()]));
+    bridged.extend_from_slice(&polygon[p..]);
+    *polygon = bridged;
+}
+
+/// Triangulates the counter-clockwise simple polygon
+fn ear_clip(points: &[Vec2], polygon: &[usize]) -> Vec<[usize; 3]> {
+    let mut remaining = polygon.to_vec();
+    let mut triangles = Vec::with_capacity(polygon.len().saturating_sub(2));
+
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        let corner = |i: usize| {
+            [
+                remaining[(i + len - 1) % len],
+                remaining[i],
+                remaining[(i + 1) % len],
+            ]
+        };
+        let convexity =
+            |[a, b, c]: [usize; 3]| (points[b] - points[a]).perp_dot(points[c] - points[b]);
+
+        let ear = (0..len).find(|i| {
+            let [a, b, c] = corner(*i);
+            let tri = [points[a], points[b], points[c]];
+            convexity([a, b, c]) > 0.0
+                && remaining
+                    .iter()
+                    .all(|p| tri.contains(&points[*p]) || !in_triangle(points[*p], tri))
+        });
+
+        // Degenerate polygons have no ears, so the most convex corner is used
+        let ear = ear.unwrap_or_else(|| {
+            (0..len)
+                .max_by(|a, b| convexity(corner(*a)).total_cmp(&convexity(corner(*b))))
+                .unwrap()
+        });
+
+        triangles.push(corner(ear));
+        remaining.remove(ear);
+    }
+
+    if remaining.len() == 3 {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+
+    triangles
+}
+
+/// Returns `true` if `p` is inside or on the edge of the counter-clockwise triangle `tri`
+fn in_triangle(p: Vec2, tri: [Vec2; 3]) -> bool {
+    (0..3).all(|i| (tri[(i + 1) % 3] - tri[i]).perp_dot(p - tri[i]) >= 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Triangulates `loops` of points, checking the triangle count and area
+    fn check(loops: &[&[[f32; 2]]]) {
+        let mut points = Vec::new();
+        let mut indices = Vec::new();
+        for l in loops {
+            let start = points.len();
+            points.extend(l.iter().map(|p| Vec2::from(*p)));
+            indices.push((start..points.len()).collect::<Vec<usize>>());
+        }
+
+        let triangles = triangulate_2d(&points, &indices);
+
+        // Every hole adds the two vertices of its bridge
+        let holes = indices
+            .iter()
+            .filter(|l| signed_area(&points, l) < 0.0)
+            .count();
+        assert_eq!(triangles.len(), points.len() - 2 + 2 * holes);
+
+        let area: f32 = indices.iter().map(|l| signed_area(&points, l)).sum();
+        let mut triangulated = 0.0;
+        for tri in triangles {
+            let tri_area = signed_area(&points, &tri);
+            assert!(tri_area >= -1e-5, "{tri:?} is clockwise");
+            triangulated += tri_area;
+        }
+        assert!((triangulated - area).abs() < 1e-4, "{triangulated} {area}");
+    }
+
+    #[test]
+    fn concave() {
+        check(&[&[
+            [0.0, 0.0],
+            [4.0, 0.0],
+            [4.0, 1.0],
+            [1.0, 1.0],
+            [1.0, 3.0],
+            [4.0, 3.0],
+            [4.0, 4.0],
+            [0.0, 4.0],
+        ]]);
+    }
+
+    #[test]
+    fn one_hole() {
+        check(&[
+            &[[0.0, 0.0], [4.0, 0.0], [4.0, 4.0], [0.0, 4.0]],
+            &[[1.0, 1.0], [1.0, 3.0], [3.0, 3.0], [3.0, 1.0]],
+        ]);
+    }
+
+    #[test]
+    fn two_holes() {
+        check(&[
+            &[[0.0, 0.0], [6.0, 0.0], [6.0, 4.0], [0.0, 4.0]],
+            &[[1.0, 1.0], [1.0, 3.0], [2.0, 3.0], [2.0, 1.0]],
+            &[[3.0, 1.0], [4.0, 3.0], [5.0, 1.0]],
+        ]);
+    }
+
+    #[test]
+    fn collinear_and_duplicate_points() {
+        check(&[&[
+            [0.0, 0.0],
+            [1.0, 0.0],
+            [2.0, 0.0],
+            [2.0, 1.0],
+            [2.0, 2.0],
+            [2.0, 2.0],
+            [1.0, 2.0],
+            [0.0, 2.0],
+            [0.0, 1.0],
+        ]]);
+    }
+}
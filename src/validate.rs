@@ -0,0 +1,255 @@
+use std::fmt;
+
+use bevy::{
+    math::{DVec3, Vec3A},
+    utils::HashMap,
+};
+
+use crate::{
+    bvh::{tri_aabb, Bvh},
+    predicates::{dvec3, tolerance},
+    GIMesh, DEFAULT_VERTEX_MERGE_DISTANCE,
+};
+
+/// The problems found by [`GIMesh::validate`]
+///
+/// Edges are the vertex indices of their ends, vertices within [`DEFAULT_VERTEX_MERGE_DISTANCE`]
+/// of each other (e.g. on UV seams) are considered the same, so they don't create boundary edges
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Edges used by only one triangle, the mesh isn't closed
+    pub boundary_edges: Vec<[u32; 2]>,
+
+    /// Edges used by more than two triangles
+    pub non_manifold_edges: Vec<[u32; 2]>,
+
+    /// Triangles with no area
+    pub degenerate_triangles: Vec<usize>,
+
+    /// The positions in [`GIMesh::indices`] of indices without a vertex
+    pub out_of_range_indices: Vec<usize>,
+
+    /// Edges used twice in the same direction, the triangles on either side face opposite ways
+    pub inconsistent_winding: Vec<[u32; 2]>,
+
+    /// Pairs of triangles crossing each other, triangles sharing a vertex aren't tested
+    ///
+    /// NOTE: not tested if there are [`ValidationReport::out_of_range_indices`]
+    pub self_intersections: Vec<[usize; 2]>,
+}
+
+impl ValidationReport {
+    /// Returns `true` if no problems were found,
+    /// the mesh is closed, consistently wound and can be used by [`crate::Boolean`]
+    pub fn is_valid(&self) -> bool {
+        self.boundary_edges.is_empty()
+            && self.non_manifold_edges.is_empty()
+            && self.degenerate_triangles.is_empty()
+            && self.out_of_range_indices.is_empty()
+            && self.inconsistent_winding.is_empty()
+            && self.self_intersections.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_valid() {
+            return write!(f, "no problems");
+        }
+
+        let problems = [
+            (self.boundary_edges.len(), "boundary edges"),
+            (self.non_manifold_edges.len(), "non-manifold edges"),
+            (self.degenerate_triangles.len(), "degenerate triangles"),
+            (self.out_of_range_indices.len(), "out of range indices"),
+            (
+                self.inconsistent_winding.len(),
+                "inconsistently wound edges",
+            ),
+            (
+                self.self_intersections.len(),
+                "self-intersecting triangle pairs",
+            ),
+        ];
+
+        let mut first = true;
+        for (count, name) in problems.into_iter().filter(|(count, _)| *count > 0) {
+            if !first {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{count} {name}")?;
+            first = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that `mesh` is closed, manifold, consistently wound and doesn't intersect itself
+pub(crate) fn validate(mesh: &GIMesh) -> ValidationReport {
+    let mut report = ValidationReport {
+        out_of_range_indices: (0..mesh.index_count())
+            .filter(|i| mesh.index(*i) >= mesh.vertex_count())
+            .collect(),
+        ..Default::default()
+    };
+
+    let (positions, position_count) = mesh.position_ids(DEFAULT_VERTEX_MERGE_DISTANCE);
+
+    // The first vertex at every position, used to report edges
+    let mut first_vertex = vec![u32::MAX; position_count];
+    for (i, p) in positions.iter().enumerate().rev() {
+        first_vertex[*p] = i as u32;
+    }
+
+    let valid_tri = |t: usize| {
+        mesh.tri(t)
+            .iter()
+            .all(|(index, _)| *index < mesh.vertex_count())
+    };
+
+    // How often every edge is used in each direction, keyed by the smaller position first
+    let mut edges: HashMap<(usize, usize), [u32; 2]> = HashMap::default();
+    for t in (0..mesh.tri_count()).filter(|t| valid_tri(*t)) {
+        let tri = mesh.tri(t).map(|(index, _)| positions[index as usize]);
+        let [a, b, c] = mesh.tri(t).map(|(index, _)| mesh.vertex(index).pos);
+        if tri[0] == tri[1]
+            || tri[1] == tri[2]
+            || tri[2] == tri[0]
+            || (b - a).cross(c - a) == Vec3A::ZERO
+        {
+            report.degenerate_triangles.push(t);
+        }
+
+        for k in 0..3 {
+            let (from, to) = (tri[k], tri[(k + 1) % 3]);
+            if from != to {
+                let uses = edges.entry((from.min(to), from.max(to))).or_default();
+                uses[(from > to) as usize] += 1;
+            }
+        }
+    }
+
+    let mut sorted: Vec<_> = edges.into_iter().collect();
+    sorted.sort_unstable_by_key(|(edge, _)| *edge);
+    for ((a, b), [forward, backward]) in sorted {
+        let edge = [first_vertex[a], first_vertex[b]];
+        match forward + backward {
+            1 => report.boundary_edges.push(edge),
+            2 if forward != backward => report.inconsistent_winding.push(edge),
+            2 => {}
+            _ => report.non_manifold_edges.push(edge),
+        }
+    }
+
+    if report.out_of_range_indices.is_empty() {
+        report.self_intersections = self_intersections(mesh, &positions);
+    }
+
+    report
+}
+
+/// Returns every pair of triangles crossing each other, skipping triangles sharing a position
+pub(crate) fn self_intersections(mesh: &GIMesh, positions: &[usize]) -> Vec<[usize; 2]> {
+    let bvh = Bvh::new(mesh);
+    let corners = |t: usize| mesh.tri(t).map(|(index, _)| dvec3(mesh.vertex(index).pos));
+
+    let mut output = Vec::new();
+    let mut candidates = Vec::new();
+    for t in 0..mesh.tri_count() {
+        let (min, max) = tri_aabb(mesh, t);
+        bvh.query_aabb(min, max, &mut candidates);
+
+        let t_positions = mesh.tri(t).map(|(index, _)| positions[index as usize]);
+        for u in candidates.iter().copied().filter(|u| *u > t) {
+            let shared = mesh
+                .tri(u)
+                .iter()
+                .any(|(index, _)| t_positions.contains(&positions[*index as usize]));
+
+            if !shared && tris_intersect(corners(t), corners(u)) {
+                output.push([t, u]);
+            }
+        }
+    }
+
+    output.sort_unstable();
+    output
+}
+
+/// Returns `true` if an edge of either triangle crosses the inside of the other
+///
+/// NOTE: triangles only touching each other or lying in the same plane aren't intersecting
+fn tris_intersect(a: [DVec3; 3], b: [DVec3; 3]) -> bool {
+    let epsilon = tolerance(&[a[0], a[1], a[2], b[0], b[1], b[2]]);
+    (0..3).any(|k| edge_crosses(a[k], a[(k + 1) % 3], b, epsilon))
+        || (0..3).any(|k| edge_crosses(b[k], b[(k + 1) % 3], a, epsilon))
+}
+
+/// Returns `true` if the edge from `p` to `q` crosses the inside of `tri`
+fn edge_crosses(p: DVec3, q: DVec3, tri: [DVec3; 3], epsilon: f64) -> bool {
+    let normal = (tri[1] - tri[0]).cross(tri[2] - tri[0]).normalize_or_zero();
+    let (side_p, side_q) = (normal.dot(p - tri[0]), normal.dot(q - tri[0]));
+    if !((side_p > epsilon && side_q < -epsilon) || (side_p < -epsilon && side_q > epsilon)) {
+        return false;
+    }
+
+    let point = p + (q - p) * (side_p / (side_p - side_q));
+    (0..3).all(|k| {
+        let edge = tri[(k + 1) % 3] - tri[k];
+        normal.dot(edge.cross(point - tri[k])) > epsilon * edge.length()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use crate::test_utils::cube;
+
+    #[test]
+    fn closed_meshes_are_valid() {
+        let report = cube(1.0, Vec3::ZERO).validate();
+        assert!(report.is_valid(), "{report}");
+    }
+
+    #[test]
+    fn fins_are_non_manifold() {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        let [(a, _), (b, _), _] = mesh.tri(0);
+        let mut fin = mesh.vertex(a).clone();
+        fin.pos += fin.normal * 2.0;
+        let c = mesh.add_vertex(fin);
+        for i in [a, b, c] {
+            mesh.add_index(i);
+        }
+
+        let report = mesh.validate();
+        assert_eq!(report.non_manifold_edges.len(), 1);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_reported() {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        mesh.indices[0] = 999;
+
+        let report = mesh.validate();
+        assert_eq!(report.out_of_range_indices, vec![0]);
+        assert!(report.to_string().contains("1 out of range indices"));
+    }
+
+    #[test]
+    fn overlapping_shells_intersect_themselves() {
+        let mut mesh = cube(1.0, Vec3::ZERO);
+        mesh.merge_with(&cube(1.0, Vec3::new(0.5, 0.25, 0.1)), &Default::default());
+
+        let report = mesh.validate();
+        assert!(report.boundary_edges.is_empty());
+        assert!(!report.self_intersections.is_empty());
+        for [a, b] in &report.self_intersections {
+            // Every pair is a triangle of the first cube crossing one of the second
+            assert!(*a < 12 && *b >= 12 || *b < 12 && *a >= 12);
+        }
+    }
+}
@@ -3,10 +3,32 @@ use std::hash::Hasher;
 use bevy::{
     math::{U16Vec4, Vec3A},
     prelude::*,
+    render::mesh::MeshVertexAttribute,
 };
 
+/// How a custom attribute is interpolated between two vertices
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    #[default]
+    Lerp,
+
+    /// The value of the closest vertex is used
+    Nearest,
+
+    /// The value isn't interpolated, e.g. for indices
+    None,
+}
+
+/// An attribute of a [`crate::GIMesh`] that isn't one of [`Vertex`]'s fields,
+/// its values are in [`Vertex::custom`]
+#[derive(Clone, Debug)]
+pub struct CustomAttribute {
+    pub attribute: MeshVertexAttribute,
+    pub interpolation: Interpolation,
+}
+
 /// Contains all the Vertex data from a [`Mesh`]
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Vertex {
     pub pos: Vec3A,
     pub normal: Vec3A,
@@ -16,11 +38,23 @@ pub struct Vertex {
     pub color: Option<Vec4>,
     pub joint_weight: Option<Vec4>,
     pub joint_index: Option<U16Vec4>,
+
+    /// The values of [`crate::GIMesh::custom_attributes`], in the same order
+    pub custom: Vec<Vec4>,
 }
 
 impl Vertex {
     /// Interpolates the value between [`self`] and [`other`]
+    ///
+    /// NOTE: custom attributes are linearly interpolated, see [`Vertex::lerp_with`]
     pub fn lerp(&mut self, other: &Vertex, s: f32) {
+        self.lerp_with(other, s, &[]);
+    }
+
+    /// Like [`Vertex::lerp`], interpolating custom attributes with the modes of `custom`
+    ///
+    /// NOTE: values without an entry in `custom` are linearly interpolated
+    pub fn lerp_with(&mut self, other: &Vertex, s: f32, custom: &[CustomAttribute]) {
         self.pos = self.pos.lerp(other.pos, s);
         self.normal = self.normal.lerp(other.normal, s);
 
@@ -45,6 +79,17 @@ impl Vertex {
         }
 
         // NOTE: [`joint_index`] can't be interpolated
+
+        for (i, (a, b)) in self.custom.iter_mut().zip(&other.custom).enumerate() {
+            match custom
+                .get(i)
+                .map_or(Interpolation::Lerp, |c| c.interpolation)
+            {
+                Interpolation::Lerp => *a = a.lerp(*b, s),
+                Interpolation::Nearest if s > 0.5 => *a = *b,
+                Interpolation::Nearest | Interpolation::None => {}
+            }
+        }
     }
 }
 
@@ -90,5 +135,12 @@ impl std::hash::Hash for Vertex {
         hasher.write_u16(joint_index.y);
         hasher.write_u16(joint_index.z);
         hasher.write_u16(joint_index.w);
+
+        for value in &self.custom {
+            hasher.write_u32(value.x.to_bits());
+            hasher.write_u32(value.y.to_bits());
+            hasher.write_u32(value.z.to_bits());
+            hasher.write_u32(value.w.to_bits());
+        }
     }
 }